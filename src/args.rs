@@ -0,0 +1,246 @@
+//! This module parses the command line.
+//!
+//! ```text
+//! toru [--file PATH | --list NAME | --global]
+//!      [-i | -s | fsck | convert FORMAT | export [PATH] | import [PATH] |
+//!       list] [--format FORMAT | --taskwarrior] [--at TASK] [--json]
+//! ```
+
+use crate::interchange::Interchange;
+use crate::storage::Format;
+use crate::ToruError;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage: toru [--file PATH | --list NAME | --global]
+            [-i | -s | fsck | convert FORMAT | export [PATH] | import [PATH] |
+             list] [--format FORMAT | --taskwarrior] [--at TASK] [--json]
+
+  --file PATH  Use the task file at PATH
+  --list NAME  Use the named task list NAME
+  --global     Use the global task file even inside a project
+  -i           Use the line based interface
+  -s           Run as a server
+  fsck         Check the task file and repair it
+  convert FORMAT
+               Rewrite the task file in the flat or nested format
+  export [PATH]
+               Copy the tasks to a new task file or SQLite database, or
+               write them to PATH or the terminal in another format
+  import [PATH]
+               Add the tasks from another task file or SQLite database, or
+               read them from PATH or the terminal in another format
+  --format FORMAT
+               Export or import tasks as md, todo.txt or opml, export tasks
+               with due dates as ics to-dos or ics-events, or import them
+               from taskwarrior, otherwise the format is taken from the
+               extension of PATH
+  --taskwarrior
+               Import the JSON written by Taskwarrior's task export
+  list         Print the current task and its subtasks
+  --at TASK    List or export the tasks below TASK, or import tasks under it
+               instead of the current task, e.g. \"Work/Report\"
+  --json       List tasks as JSON, with list or in the line based interface";
+
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    /// The TUI, or the line based interface without a terminal.
+    Default,
+    Cli,
+    Server,
+    Fsck,
+    Convert(Format),
+    /// Copy the tree to a new file, or write it out in another format to a
+    /// file or the terminal.
+    Export(Option<PathBuf>),
+    /// Add the tasks of another file to the tree, or read them in another
+    /// format from a file or the terminal.
+    Import(Option<PathBuf>),
+    /// Print a task and its subtasks.
+    List,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub file: Option<PathBuf>,
+    pub list: Option<String>,
+    /// Skip looking for a project's task file.
+    pub global: bool,
+    pub mode: Mode,
+    /// The format to export or import tasks in.
+    pub format: Option<Interchange>,
+    /// The names of the tasks leading to the task to list or export, or to
+    /// import tasks under.
+    pub at: Option<String>,
+    /// List tasks as JSON.
+    pub json: bool,
+}
+
+/// Parses the arguments following the program name. Options may be written
+/// as either `--file PATH` or `--file=PATH`.
+pub fn parse<I>(args: I) -> Result<Args, ToruError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut parsed = Args {
+        file: None,
+        list: None,
+        global: false,
+        mode: Mode::Default,
+        format: None,
+        at: None,
+        json: false,
+    };
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => {
+                (name.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+
+        let mut value = || {
+            inline.clone().or_else(|| args.next()).ok_or_else(|| {
+                ToruError::InvalidArgument(format!("{} needs a value", name))
+            })
+        };
+
+        match name.as_str() {
+            "--file" => parsed.file = Some(PathBuf::from(value()?)),
+            "--list" => parsed.list = Some(value()?),
+            "--global" => parsed.global = true,
+            "--format" => parsed.format = Some(value()?.parse()?),
+            "--taskwarrior" => parsed.format = Some(Interchange::Taskwarrior),
+            "--at" => parsed.at = Some(value()?),
+            "--json" => parsed.json = true,
+            "-i" | "-s" | "fsck" | "convert" | "export" | "import" | "list"
+                if parsed.mode != Mode::Default =>
+            {
+                return Err(ToruError::InvalidArgument(format!(
+                    "Unexpected argument '{}'",
+                    name
+                )));
+            }
+            "-i" => parsed.mode = Mode::Cli,
+            "-s" => parsed.mode = Mode::Server,
+            "fsck" => parsed.mode = Mode::Fsck,
+            "convert" => parsed.mode = Mode::Convert(value()?.parse()?),
+            "export" => parsed.mode = Mode::Export(None),
+            "import" => parsed.mode = Mode::Import(None),
+            "list" => parsed.mode = Mode::List,
+            _ if parsed.mode == Mode::Export(None)
+                && !name.starts_with('-') =>
+            {
+                parsed.mode = Mode::Export(Some(PathBuf::from(name)));
+            }
+            _ if parsed.mode == Mode::Import(None)
+                && !name.starts_with('-') =>
+            {
+                parsed.mode = Mode::Import(Some(PathBuf::from(name)));
+            }
+            _ => {
+                return Err(ToruError::InvalidArgument(format!(
+                    "Unknown argument '{}'",
+                    name
+                )));
+            }
+        }
+    }
+
+    if parsed.file.is_some() && parsed.list.is_some() {
+        return Err(ToruError::InvalidArgument(String::from(
+            "--file and --list cannot be used together",
+        )));
+    }
+
+    let copying = matches!(parsed.mode, Mode::Export(_) | Mode::Import(_));
+    if parsed.format.is_some() && !copying {
+        return Err(ToruError::InvalidArgument(String::from(
+            "--format and --taskwarrior are only used with export and import",
+        )));
+    }
+    if parsed.at.is_some() && !copying && parsed.mode != Mode::List {
+        return Err(ToruError::InvalidArgument(String::from(
+            "--at is only used with list, export and import",
+        )));
+    }
+    if parsed.json && !matches!(parsed.mode, Mode::List | Mode::Cli) {
+        return Err(ToruError::InvalidArgument(String::from(
+            "--json is only used with list and -i",
+        )));
+    }
+    if parsed.mode == Mode::Export(None) && parsed.format.is_none() {
+        return Err(ToruError::InvalidArgument(String::from(
+            "export needs a PATH or a --format to write to the terminal",
+        )));
+    }
+    if parsed.mode == Mode::Import(None) && parsed.format.is_none() {
+        return Err(ToruError::InvalidArgument(String::from(
+            "import needs a PATH or a --format to read from the terminal",
+        )));
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_str(s: &str) -> Result<Args, ToruError> {
+        parse(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn options_and_modes() {
+        let args = parse_str("--list work -i").unwrap();
+        assert_eq!(args.list.as_deref(), Some("work"));
+        assert_eq!(args.mode, Mode::Cli);
+
+        assert!(parse_str("--global").unwrap().global);
+
+        let args = parse_str("fsck --file=/tmp/tasks.yaml").unwrap();
+        assert_eq!(args.file, Some(PathBuf::from("/tmp/tasks.yaml")));
+        assert_eq!(args.mode, Mode::Fsck);
+
+        let args = parse_str("convert nested").unwrap();
+        assert_eq!(args.mode, Mode::Convert(Format::Nested));
+
+        let args = parse_str("export tasks.db").unwrap();
+        assert_eq!(args.mode, Mode::Export(Some(PathBuf::from("tasks.db"))));
+
+        let args = parse_str("export --format md --at Work").unwrap();
+        assert_eq!(args.mode, Mode::Export(None));
+        assert_eq!(args.format, Some(Interchange::Markdown));
+        assert_eq!(args.at.as_deref(), Some("Work"));
+
+        let args = parse_str("list --at Work --json").unwrap();
+        assert_eq!(args.mode, Mode::List);
+        assert!(args.json);
+
+        let args = parse_str("import --at Work notes.md").unwrap();
+        assert_eq!(args.mode, Mode::Import(Some(PathBuf::from("notes.md"))));
+
+        let args = parse_str("import --taskwarrior").unwrap();
+        assert_eq!(args.mode, Mode::Import(None));
+        assert_eq!(args.format, Some(Interchange::Taskwarrior));
+
+        assert_eq!(parse_str("").unwrap().mode, Mode::Default);
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(parse_str("--list").is_err());
+        assert!(parse_str("--file a --list b").is_err());
+        assert!(parse_str("-i -s").is_err());
+        assert!(parse_str("--verbose").is_err());
+        assert!(parse_str("convert yaml").is_err());
+        assert!(parse_str("import").is_err());
+        assert!(parse_str("export a import b").is_err());
+        assert!(parse_str("export").is_err());
+        assert!(parse_str("export a b").is_err());
+        assert!(parse_str("-i --format md").is_err());
+        assert!(parse_str("fsck --json").is_err());
+    }
+}
@@ -1,16 +1,14 @@
-use std::env;
-
-use crate::task::Task;
+use crate::json;
+use crate::storage::Storage;
+use crate::task::{self, Task};
 use crate::tree::{self, Tree};
 use crate::ToruError;
 
 use std::{
     error::Error,
     fmt,
-    fs::File,
     io::{self, Write},
     num::ParseIntError,
-    path::{Path, PathBuf},
     str::FromStr,
     time::SystemTime,
 };
@@ -60,13 +58,16 @@ pub enum Command {
     Complete,
     Delete,
     Descend,
+    EmptyTrash,
     List,
+    Restore,
+    Trash,
     Help,
     Exit,
 }
 
 impl Command {
-    fn run(self, io: &mut IO, tree: Tree) -> Tree {
+    fn run(self, io: &mut IO, tree: Tree, json: bool) -> Tree {
         match self {
             Self::Add => {
                 let parent_idx = tree.ptr();
@@ -80,8 +81,18 @@ impl Command {
             }
             Self::Ascend => tree::ascend(tree),
             Self::Complete => verify_index_and(io, tree, tree::complete),
-            Self::Delete => verify_index_and(io, tree, tree::delete),
+            Self::Delete => verify_index_and(io, tree, tree::trash),
             Self::Descend => verify_index_and(io, tree, tree::descend),
+            Self::EmptyTrash => tree::empty_trash(tree),
+            Self::Restore => verify_index_and(io, tree, tree::restore),
+            Self::Trash => tree::goto_trash(tree),
+            Self::List if json => {
+                match json::list(&tree, tree.ptr()) {
+                    Ok(listing) => io.writeln(listing),
+                    Err(e) => eprintln!("{}", e),
+                }
+                tree
+            }
             Self::List => {
                 list(io, &tree);
                 tree
@@ -105,6 +116,9 @@ impl FromStr for Command {
             "del" => Ok(Self::Delete),
             "done" => Ok(Self::Complete),
             "down" => Ok(Self::Descend),
+            "empty" => Ok(Self::EmptyTrash),
+            "restore" => Ok(Self::Restore),
+            "trash" => Ok(Self::Trash),
             "list" => Ok(Self::List),
             "help" => Ok(Self::Help),
             "exit" => Ok(Self::Exit),
@@ -113,28 +127,39 @@ impl FromStr for Command {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct CLI {
-    save_path: PathBuf,
+    file: Box<dyn Storage>,
     io: IO,
     tree: Tree,
+    /// List tasks as JSON rather than for reading.
+    json: bool,
 }
 
 impl CLI {
-    pub fn new(save_path: PathBuf, tree: Tree) -> Self {
+    pub fn new(file: Box<dyn Storage>, tree: Tree) -> Self {
         Self {
-            save_path,
+            file,
             io: IO::new(),
             tree,
+            json: false,
         }
     }
 
+    /// Sets whether the `list` command writes JSON, see the [`json`] module.
+    ///
+    /// [`json`]: ../json/index.html
+    pub fn set_json(mut self, json: bool) -> Self {
+        self.json = json;
+        self
+    }
+
     pub fn run(mut self) -> Result<(), Box<dyn Error>> {
         loop {
             self.io.write("toru> ");
             self.io.buffer.clear();
 
-            let bytes_read = io::stdin()
-                .read_line(&mut self.io.buffer)?;
+            let bytes_read = io::stdin().read_line(&mut self.io.buffer)?;
 
             if bytes_read == 0 {
                 break;
@@ -150,59 +175,32 @@ impl CLI {
                 break;
             }
 
-            self.tree = cmd.run(&mut self.io, self.tree);
+            self.tree = cmd.run(&mut self.io, self.tree, self.json);
         }
 
         self.io.writeln("Saving...");
-        match File::create(&self.save_path) {
-            Ok(file) => serde_yaml::to_writer(file, &self.tree).unwrap(),
-            Err(e) => {
-                eprintln!("{}", e);
-            }
-        }
-
-        Ok(())
-    }
-}
-
-impl Default for CLI {
-    fn default() -> Self {
-        let file_name = Path::new(".toru.yaml");
-        let key = if cfg!(windows) { "HOMEPATH" } else { "HOME" };
-
-        let path = match env::var(key) {
-            Ok(home) => Path::new(&home).join(file_name),
-            Err(_) => file_name.to_path_buf(),
-        };
-
-        let file = File::open(&path);
-
-        let tree = if file.is_ok() {
-            let file = file.unwrap();
-            serde_yaml::from_reader::<_, Tree>(file).unwrap()
-        } else {
-            Tree::new()
-        };
-
-        Self::new(path, tree)
+        self.file.save(&self.tree)
     }
 }
 
 pub fn help(io: &mut IO) {
     let help = vec![
         "add - Add a task.",
-        "delete - Delete a task.",
+        "del - Move a task to the trash.",
         "done - Complete a task.",
         "down - Traverse 'down' into a task.",
+        "empty - Permanently delete everything in the trash.",
         "exit - Exit toru.",
         "help - Show the help message.",
         "list - Print current task and its children",
+        "restore - Restore a task from the trash.",
+        "trash - Traverse into the trash.",
         "up - Traverse 'up' to a tasks' parent",
     ];
 
     io.writeln(String::from("\nToru help:"));
     for msg in help {
-        io.writeln(format!("{}", msg));
+        io.writeln(msg);
     }
     io.writeln(String::from(""));
 }
@@ -239,13 +237,12 @@ pub fn task_from_stdin(
     io: &mut IO,
     parent_idx: usize,
 ) -> Result<Task, time::ParseError> {
-    let date_format = "%F %I:%M %p";
     let now = PrimitiveDateTime::from(SystemTime::now());
 
     io.write(String::from("Name> "));
     let name = io.readln();
 
-    io.write(format!("Due [{}]", now.format(date_format)));
+    io.write(format!("Due [{}]", now.format(task::DATE_FORMAT)));
     let date = io.readln();
 
     let date = date.trim_end();
@@ -256,7 +253,7 @@ pub fn task_from_stdin(
     Ok(if date.is_empty() {
         task
     } else {
-        task.set_due(time::parse(date, date_format)?)
+        task.set_due(task::parse_due(date)?)
     })
 }
 
@@ -281,5 +278,5 @@ where
 
 fn index_from_stdin(io: &mut IO) -> Result<usize, ParseIntError> {
     io.write("Index> ");
-    io.readln().trim_end().parse::<usize>().and_then(|idx| Ok(idx - 1))
+    io.readln().trim_end().parse::<usize>().map(|idx| idx - 1)
 }
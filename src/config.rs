@@ -0,0 +1,102 @@
+//! This module loads the user's [`Config`] file. The file is YAML and lives
+//! at `$XDG_CONFIG_HOME/toru/config.yaml`, or `~/.config/toru/config.yaml`
+//! when `XDG_CONFIG_HOME` is not set. Every field is optional.
+//!
+//! ```yaml
+//! # Start from an empty keymap instead of the default bindings
+//! default_keys: false
+//! # Keys mapped to TUI actions. Sequences are written with spaces between
+//! # the keys, e.g. "g t", or as "gg" when one key is repeated. Mapping a key
+//! # to "none" removes its default binding.
+//! keys:
+//!   ctrl-n: next
+//!   ctrl-p: previous
+//!   j: none
+//! ```
+//!
+//! [`Config`]: ./struct.Config.html
+
+use crate::tui::keymap::Keymap;
+use crate::ToruError;
+use serde::Deserialize;
+use std::{collections::BTreeMap, env, fs, io, path::PathBuf};
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    default_keys: bool,
+    keys: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Builds the TUI keymap from the default bindings and the user's keys.
+    pub fn keymap(&self) -> Result<Keymap, ToruError> {
+        let mut keymap = if self.default_keys {
+            Keymap::default()
+        } else {
+            Keymap::empty()
+        };
+
+        for (keys, action) in self.keys.iter() {
+            keymap.bind(keys, action).map_err(|e| match e {
+                ToruError::ConfigError(msg) => {
+                    ToruError::ConfigError(format!("Key '{}': {}", keys, msg))
+                }
+                e => e,
+            })?;
+        }
+
+        Ok(keymap)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_keys: true,
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    let directory = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let key = if cfg!(windows) { "HOMEPATH" } else { "HOME" };
+            PathBuf::from(env::var(key).ok()?).join(".config")
+        }
+    };
+
+    Some(directory.join("toru").join("config.yaml"))
+}
+
+/// Loads the config file, falling back to the defaults when there is none.
+pub fn load() -> Result<Config, ToruError> {
+    let path = match path() {
+        Some(path) => path,
+        None => return Ok(Config::default()),
+    };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Config::default())
+        }
+        Err(e) => {
+            return Err(ToruError::ConfigError(format!(
+                "{}: {}",
+                path.display(),
+                e
+            )))
+        }
+    };
+
+    if contents.trim().is_empty() {
+        return Ok(Config::default());
+    }
+
+    serde_yaml::from_str(&contents).map_err(|e| {
+        ToruError::ConfigError(format!("{}: {}", path.display(), e))
+    })
+}
@@ -0,0 +1,331 @@
+//! This module checks that a [`Tree`] read from disk is sound and repairs it
+//! when it is not. A sound tree has
+//!
+//! - a root at index 0, which like the trash has no parent,
+//! - no indices referring to tasks which do not exist,
+//! - every other task listed as the child of exactly one task, which is also
+//!   the parent the task records,
+//! - no task which is its own ancestor.
+//!
+//! [`Tree`]: ../tree/struct.Tree.html
+
+use crate::storage::Storage;
+use crate::task::Task;
+use crate::tree::Tree;
+use crate::Result;
+
+/// The structure a tree is repaired to, along with what was wrong with it.
+struct Plan {
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    trashed_from: Vec<Option<usize>>,
+    visited: Vec<bool>,
+    ptr: usize,
+    trash: Option<usize>,
+    problems: Vec<String>,
+}
+
+impl Plan {
+    /// Walks down from a task through the children each task lists. A task is
+    /// kept under the first parent found to list it, so that a task listed
+    /// twice or listed by one of its own descendants ends up in one place.
+    fn walk(&mut self, tasks: &[Task], top: usize) {
+        self.visited[top] = true;
+        let mut stack = vec![top];
+
+        while let Some(idx) = stack.pop() {
+            for &child in tasks[idx].children() {
+                if child >= tasks.len() {
+                    self.problems.push(format!(
+                        "Task {} lists child {} which does not exist",
+                        idx, child
+                    ));
+                } else if self.visited[child] {
+                    self.problems.push(format!(
+                        "Task {} lists child {} which is already in the tree",
+                        idx, child
+                    ));
+                } else {
+                    self.visited[child] = true;
+                    self.parents[child] = Some(idx);
+                    self.children[idx].push(child);
+                    stack.push(child);
+                }
+            }
+        }
+    }
+}
+
+fn plan(tree: &Tree) -> Plan {
+    let tasks = tree.tasks();
+    let length = tasks.len();
+    let mut plan = Plan {
+        parents: vec![None; length],
+        children: vec![Vec::new(); length],
+        trashed_from: tasks.iter().map(|t| t.trashed_from()).collect(),
+        visited: vec![false; length],
+        ptr: tree.ptr(),
+        trash: tree.trash(),
+        problems: Vec::new(),
+    };
+
+    if plan.ptr >= length {
+        plan.problems
+            .push(format!("The current task {} does not exist", plan.ptr));
+        plan.ptr = 0;
+    }
+
+    match plan.trash {
+        Some(trash) if trash == 0 || trash >= length => {
+            plan.problems
+                .push(format!("The trash {} does not exist", trash));
+            plan.trash = None;
+        }
+        _ => {}
+    }
+
+    plan.walk(tasks, 0);
+    if let Some(trash) = plan.trash {
+        if plan.visited[trash] {
+            plan.problems
+                .push(format!("The trash {} is listed as a child", trash));
+
+            // The trash is taken from the task listing it, and its tasks are
+            // walked again from the trash
+            if let Some(parent) = plan.parents[trash].take() {
+                plan.children[parent].retain(|&child| child != trash);
+            }
+            let mut stack = vec![trash];
+            while let Some(idx) = stack.pop() {
+                plan.visited[idx] = false;
+                stack.append(&mut plan.children[idx]);
+            }
+        }
+        plan.walk(tasks, trash);
+    }
+
+    // Tasks which no parent lists are put back where they claim to belong,
+    // or under the root when that is not possible
+    let mut moved = Vec::new();
+    for idx in 0..length {
+        if plan.visited[idx] {
+            continue;
+        }
+
+        let mut top = idx;
+        let mut climbed = vec![idx];
+        while let Some(parent) = tasks[top].parent() {
+            if parent >= length || plan.visited[parent] {
+                break;
+            }
+            if climbed.contains(&parent) {
+                // The parents form a cycle, which is broken at this task
+                top = idx;
+                break;
+            }
+            top = parent;
+            climbed.push(parent);
+        }
+
+        let parent = match tasks[top].parent() {
+            Some(parent) if parent < length && plan.visited[parent] => parent,
+            _ => 0,
+        };
+
+        plan.problems.push(format!(
+            "Task {} is not listed by its parent, moved under task {}",
+            top, parent
+        ));
+        plan.parents[top] = Some(parent);
+        plan.children[parent].push(top);
+        plan.walk(tasks, top);
+        moved.push(top);
+    }
+
+    for (idx, task) in tasks.iter().enumerate() {
+        if task.parent() != plan.parents[idx] && !moved.contains(&idx) {
+            plan.problems.push(format!(
+                "Task {} records {} as its parent instead of {}",
+                idx,
+                describe(task.parent()),
+                describe(plan.parents[idx])
+            ));
+        }
+    }
+
+    for (idx, from) in plan.trashed_from.iter_mut().enumerate() {
+        if let Some(parent) = *from {
+            if parent >= length {
+                plan.problems.push(format!(
+                    "Task {} was trashed from task {} which does not exist",
+                    idx, parent
+                ));
+                *from = None;
+            }
+        }
+    }
+
+    plan
+}
+
+fn describe(parent: Option<usize>) -> String {
+    match parent {
+        Some(idx) => format!("task {}", idx),
+        None => String::from("no task"),
+    }
+}
+
+/// Returns a description of everything wrong with a tree. A sound tree has
+/// no problems.
+pub fn check(tree: &Tree) -> Vec<String> {
+    if tree.tasks().is_empty() {
+        return vec![String::from("The tree has no root")];
+    }
+
+    plan(tree).problems
+}
+
+/// Repairs a tree, returning it along with a description of what was wrong.
+pub fn repair(mut tree: Tree) -> (Tree, Vec<String>) {
+    let mut problems = Vec::new();
+    if tree.tasks().is_empty() {
+        problems.push(String::from("The tree has no root"));
+        tree.tasks_mut().push(Task::new());
+    }
+
+    let plan = plan(&tree);
+    problems.extend(plan.problems);
+
+    let tasks = std::mem::take(tree.tasks_mut());
+    *tree.tasks_mut() = tasks
+        .into_iter()
+        .zip(plan.parents)
+        .zip(plan.children)
+        .zip(plan.trashed_from)
+        .map(|(((task, parent), children), trashed_from)| {
+            let task = match parent {
+                Some(parent) => task.set_parent(parent),
+                None => task.remove_parent(),
+            };
+            task.set_children(children).set_trashed_from(trashed_from)
+        })
+        .collect();
+
+    tree.set_ptr(plan.ptr);
+    tree.set_trash(plan.trash);
+
+    (tree, problems)
+}
+
+/// Checks the task file at `path` and saves the repaired tree if anything was
+/// wrong with it. A damaged file which keeps backups is kept as the latest
+/// one.
+pub fn run(file: &mut dyn Storage) -> Result<()> {
+    let path = file.path().to_path_buf();
+    let tree = file.load_unchecked()?;
+    let (tree, problems) = repair(tree);
+
+    if problems.is_empty() {
+        println!("{}: no problems found", path.display());
+        return Ok(());
+    }
+
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+
+    file.save(&tree)?;
+    print!(
+        "Repaired {} problem(s) in {}",
+        problems.len(),
+        path.display()
+    );
+    match file.backup() {
+        Some(backup) => {
+            println!(", the damaged file was kept as {}", backup.display())
+        }
+        None => println!(),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::named;
+    use crate::tree;
+
+    #[test]
+    fn sound_tree_has_no_problems() {
+        let mut tree = Tree::new();
+        tree = tree::add(tree, named("a"));
+        tree = tree::add(tree, named("b"));
+        tree = tree::descend(tree, 1);
+        tree = tree::add(tree, named("c"));
+        tree = tree::trash(tree, 3);
+
+        assert!(check(&tree).is_empty());
+    }
+
+    #[test]
+    fn repair_damaged_tree() {
+        let mut tree = Tree::new();
+        tree = tree::add(tree, named("a"));
+        tree = tree::add(tree, named("b"));
+
+        // 0 lists a missing task and 1 lists the root, while 2 is a child of
+        // 1 that 1 does not list
+        *tree.tasks_mut() = vec![
+            Task::new().add_child(1).add_child(9),
+            named("a").set_parent(0).add_child(0),
+            named("b").set_parent(1),
+        ];
+        tree.set_ptr(7);
+
+        let (tree, problems) = repair(tree);
+        assert_eq!(problems.len(), 4);
+        assert!(check(&tree).is_empty());
+
+        assert_eq!(tree.ptr(), 0);
+        assert_eq!(tree.tasks()[0].children(), &vec![1]);
+        assert_eq!(tree.tasks()[1].children(), &vec![2]);
+        assert_eq!(tree.tasks()[2].parent(), Some(1));
+    }
+
+    #[test]
+    fn repair_unreachable_cycle() {
+        let tasks = vec![
+            Task::new(),
+            named("a").set_parent(2).add_child(2),
+            named("b").set_parent(1).add_child(1),
+        ];
+        let mut tree = Tree::new();
+        *tree.tasks_mut() = tasks;
+
+        let (tree, _) = repair(tree);
+        assert!(check(&tree).is_empty());
+        assert_eq!(tree.tasks()[0].children(), &vec![1]);
+        assert_eq!(tree.tasks()[1].children(), &vec![2]);
+        assert_eq!(tree.tasks()[2].children(), &Vec::<usize>::new());
+    }
+
+    #[test]
+    fn repair_trash_listed_as_child() {
+        let mut tree = Tree::new();
+        tree = tree::add(tree, named("a"));
+        tree = tree::add(tree, named("b"));
+        tree = tree::trash(tree, 2);
+        let trash = tree.trash().unwrap();
+        let a = tree.tasks()[1].clone().add_child(trash);
+        let mut tree = tree.replace_task(1, a);
+        tree.tasks_mut()[trash] = tree.tasks()[trash].clone().set_parent(1);
+
+        let (tree, problems) = repair(tree);
+        assert!(!problems.is_empty());
+        assert!(check(&tree).is_empty());
+        assert_eq!(tree.tasks()[1].children(), &Vec::<usize>::new());
+        assert_eq!(tree.tasks()[trash].parent(), None);
+        assert_eq!(tree.tasks()[trash].children().len(), 1);
+    }
+}
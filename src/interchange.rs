@@ -0,0 +1,139 @@
+//! This module converts trees to and from the formats other tools keep task
+//! lists in, for `toru export` and `toru import`. Unlike a task file these
+//! formats do not keep everything about a tree, e.g. none of them has a trash,
+//! so they are only used to copy tasks in and out of toru.
+
+mod ical;
+mod markdown;
+mod opml;
+mod taskwarrior;
+mod todotxt;
+
+use crate::task::Task;
+use crate::tree::{self, Tree};
+use crate::{Result, ToruError};
+use ical::Component;
+use std::{fmt, path::Path, str::FromStr};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interchange {
+    /// Nested GitHub style checklists.
+    Markdown,
+    /// A task on each line, see todotxt.org.
+    TodoTxt,
+    /// An iCalendar file with the tasks which have due dates, written as
+    /// to-dos or as events.
+    ICalendar(Component),
+    /// An OPML outline, as outliners and mind mapping tools write it.
+    Opml,
+    /// The JSON written by Taskwarrior's `task export`.
+    Taskwarrior,
+}
+
+impl Interchange {
+    /// Guesses the format of a file from its extension.
+    pub fn detect(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "md" | "markdown" => Some(Self::Markdown),
+            "txt" => Some(Self::TodoTxt),
+            "ics" => Some(Self::ICalendar(Component::Todo)),
+            "opml" => Some(Self::Opml),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Interchange {
+    type Err = ToruError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "md" | "markdown" => Ok(Self::Markdown),
+            "todo.txt" | "todotxt" => Ok(Self::TodoTxt),
+            "ics" => Ok(Self::ICalendar(Component::Todo)),
+            "ics-events" => Ok(Self::ICalendar(Component::Event)),
+            "opml" => Ok(Self::Opml),
+            "taskwarrior" => Ok(Self::Taskwarrior),
+            _ => Err(Self::Err::InvalidArgument(format!(
+                "Unknown format '{}', expected md, todo.txt, ics, ics-events, \
+                 opml or taskwarrior",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Interchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Markdown => write!(f, "md"),
+            Self::TodoTxt => write!(f, "todo.txt"),
+            Self::ICalendar(Component::Todo) => write!(f, "ics"),
+            Self::ICalendar(Component::Event) => write!(f, "ics-events"),
+            Self::Opml => write!(f, "opml"),
+            Self::Taskwarrior => write!(f, "taskwarrior"),
+        }
+    }
+}
+
+/// Finds the task at the end of a path of names below the root, adding the
+/// tasks which do not exist yet. Names are compared by their `key`, for
+/// formats which cannot write every name as it is.
+fn add_path<I>(
+    mut tree: Tree,
+    names: I,
+    key: fn(&str) -> String,
+) -> (Tree, usize)
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut idx = 0;
+    for name in names.into_iter().filter(|name| !name.is_empty()) {
+        let existing = tree.tasks()[idx]
+            .children()
+            .iter()
+            .copied()
+            .find(|&child| key(tree.tasks()[child].name()) == key(&name));
+
+        idx = match existing {
+            Some(child) => child,
+            None => {
+                let child = tree.tasks().len();
+                tree.set_ptr(idx);
+                tree = tree::add(tree, Task::new().set_name(name));
+                child
+            }
+        };
+    }
+
+    (tree, idx)
+}
+
+/// Renders the tasks below the task at `idx`.
+pub fn export(tree: &Tree, idx: usize, format: Interchange) -> Result<String> {
+    match format {
+        Interchange::Markdown => Ok(markdown::export(tree, idx)),
+        Interchange::TodoTxt => Ok(todotxt::export(tree, idx)),
+        Interchange::ICalendar(component) => {
+            Ok(ical::export(tree, idx, component))
+        }
+        Interchange::Opml => Ok(opml::export(tree, idx)),
+        Interchange::Taskwarrior => {
+            Err(format!("Tasks cannot be exported as {}", format).into())
+        }
+    }
+}
+
+/// Reads tasks into a tree of their own, which can then be grafted onto
+/// another.
+pub fn import(contents: &str, format: Interchange) -> Result<Tree> {
+    match format {
+        Interchange::Markdown => Ok(markdown::import(contents)),
+        Interchange::TodoTxt => Ok(todotxt::import(contents)),
+        Interchange::ICalendar(_) => {
+            Err(format!("Tasks cannot be imported from {}", format).into())
+        }
+        Interchange::Opml => opml::import(contents),
+        Interchange::Taskwarrior => taskwarrior::import(contents),
+    }
+}
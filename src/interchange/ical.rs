@@ -0,0 +1,210 @@
+//! This module writes the tasks which have a due date as an [iCalendar] file,
+//! either as to-dos or as events for calendars which only show events.
+//!
+//! ```text
+//! BEGIN:VTODO
+//! UID:5d1c0a8e4a3f2b71@toru
+//! DTSTAMP:20210301T090000Z
+//! SUMMARY:Draft
+//! DUE:20210301T170000
+//! STATUS:NEEDS-ACTION
+//! RELATED-TO:0c9a5e2f7d6b4e13@toru
+//! END:VTODO
+//! ```
+//!
+//! A task's UID is the uid it keeps in the tree, so it stays the same from one
+//! export to the next, even when the task is renamed or moved, and a calendar
+//! subscribed to the file updates its entries rather than duplicating them.
+//! A subtask refers to its parent with `RELATED-TO`, even if the parent has no
+//! due date. Due dates at midnight are written as dates, which calendars show
+//! as all day.
+//!
+//! [iCalendar]: https://tools.ietf.org/html/rfc5545
+
+use crate::tree::Tree;
+use time::{OffsetDateTime, PrimitiveDateTime, Time};
+
+/// The component each task is written as.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Component {
+    Todo,
+    Event,
+}
+
+/// Returns the UID of a task, from the uid it keeps in the tree.
+fn uid(tree: &Tree, idx: usize) -> String {
+    format!("{:016x}@toru", tree.uid(idx))
+}
+
+/// Escapes the characters which have a meaning in a text value.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Writes a content line, folding it so that no line is longer than 75
+/// bytes.
+fn write_line(out: &mut String, line: &str) {
+    let mut start = 0;
+    let mut width = 75;
+
+    for (i, c) in line.char_indices() {
+        if i + c.len_utf8() - start > width {
+            out.push_str(&line[start..i]);
+            out.push_str("\r\n ");
+            start = i;
+            // The space starting a folded line counts towards its length
+            width = 74;
+        }
+    }
+
+    out.push_str(&line[start..]);
+    out.push_str("\r\n");
+}
+
+fn date_value(due: &PrimitiveDateTime) -> String {
+    if due.time() == Time::midnight() {
+        format!(";VALUE=DATE:{}", due.format("%Y%m%d"))
+    } else {
+        format!(":{}", due.format("%Y%m%dT%H%M%S"))
+    }
+}
+
+/// Writes every task with a due date below the task at `idx`.
+pub fn export(tree: &Tree, idx: usize, component: Component) -> String {
+    let stamp = OffsetDateTime::now_utc().format("%Y%m%dT%H%M%SZ");
+    let (name, due) = match component {
+        Component::Todo => ("VTODO", "DUE"),
+        Component::Event => ("VEVENT", "DTSTART"),
+    };
+
+    let mut out = String::new();
+    write_line(&mut out, "BEGIN:VCALENDAR");
+    write_line(&mut out, "VERSION:2.0");
+    write_line(&mut out, "PRODID:-//toru//toru//EN");
+
+    for task_idx in tree.subtree(idx).into_iter().skip(1) {
+        let task = &tree.tasks()[task_idx];
+        let task_due = match task.due() {
+            Some(due) => due,
+            None => continue,
+        };
+
+        let mut lines = vec![
+            format!("BEGIN:{}", name),
+            format!("UID:{}", uid(tree, task_idx)),
+            format!("DTSTAMP:{}", stamp),
+            format!("SUMMARY:{}", escape(task.name())),
+            format!("{}{}", due, date_value(task_due)),
+        ];
+        let status = match (component, task.is_complete()) {
+            (Component::Todo, true) => "COMPLETED",
+            (Component::Todo, false) => "NEEDS-ACTION",
+            (Component::Event, _) => "CONFIRMED",
+        };
+        lines.push(format!("STATUS:{}", status));
+        if !task.tags().is_empty() {
+            let tags: Vec<String> =
+                task.tags().iter().map(|tag| escape(tag)).collect();
+            lines.push(format!("CATEGORIES:{}", tags.join(",")));
+        }
+        if let Some(priority) = task.priority() {
+            // A is 1, the highest, and anything past I is 9, the lowest
+            let priority = (priority as u32).saturating_sub('A' as u32) + 1;
+            lines.push(format!("PRIORITY:{}", priority.min(9)));
+        }
+        if let Some(parent) = task.parent().filter(|&parent| parent != 0) {
+            lines.push(format!("RELATED-TO:{}", uid(tree, parent)));
+        }
+        lines.push(format!("END:{}", name));
+
+        for line in lines {
+            write_line(&mut out, &line);
+        }
+    }
+
+    write_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::task;
+    use crate::test_util::named;
+    use crate::tree;
+
+    fn dated_tree() -> Tree {
+        let due = task::parse_due("2021-03-01 05:00 pm").unwrap();
+        let mut tree = Tree::new();
+        tree = tree::add(tree, named("Work"));
+        tree.set_ptr(1);
+        tree = tree::add(tree, named("Draft, then review").set_due(due));
+        tree = tree::add(tree, named("Party").set_due(due.date().midnight()));
+        tree = tree::add(tree, named("Someday"));
+        tree = tree::complete(tree, 3);
+        tree
+    }
+
+    #[test]
+    fn dated_tasks_are_todos() {
+        let tree = dated_tree();
+        let ics = export(&tree, 0, Component::Todo);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VTODO").count(), 2);
+        assert!(ics.contains("SUMMARY:Draft\\, then review\r\n"));
+        assert!(ics.contains("DUE:20210301T170000\r\n"));
+        assert!(ics.contains("DUE;VALUE=DATE:20210301\r\n"));
+        assert!(ics.contains("STATUS:COMPLETED\r\n"));
+        assert!(!ics.contains("Someday"));
+
+        let related = format!("RELATED-TO:{}\r\n", uid(&tree, 1));
+        assert_eq!(ics.matches(&related).count(), 2);
+
+        let events = export(&tree, 0, Component::Event);
+        assert!(events.contains("DTSTART:20210301T170000\r\n"));
+        assert!(!events.contains("VTODO"));
+    }
+
+    #[test]
+    fn uids_are_stable() {
+        let tree = dated_tree();
+        let uids: Vec<String> = (1..5).map(|idx| uid(&tree, idx)).collect();
+
+        // Renaming or moving a task keeps its UID
+        let mut other = tree.clone();
+        other.tasks_mut()[2] =
+            other.tasks()[2].clone().set_name(String::from("Draft"));
+        other = tree::relocate(other, 3, 0);
+        assert_eq!(
+            uids,
+            (1..5).map(|idx| uid(&other, idx)).collect::<Vec<_>>()
+        );
+
+        // A task with the same name as another is told apart
+        other.set_ptr(1);
+        other = tree::add(other, named("Draft"));
+        assert_ne!(uid(&other, 2), uid(&other, 5));
+
+        let mut lines = String::new();
+        write_line(&mut lines, &"x".repeat(160));
+        assert!(lines.split("\r\n").all(|line| line.len() <= 75));
+        assert_eq!(
+            lines.replace("\r\n ", ""),
+            format!("{}\r\n", "x".repeat(160))
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! This module renders tasks as nested GitHub style checklists, ready to be
+//! pasted into an issue or a pull request, and reads them back from issue
+//! templates and notes.
+//!
+//! ```markdown
+//! - [ ] Write report (due 2021-03-01 05:00 pm)
+//!   - [x] Gather figures
+//!   - [ ] Draft
+//! ```
+//!
+//! Reading a checklist picks out the items with a checkbox and ignores any
+//! other text. An item is a subtask of the item above it that is indented
+//! less, however deep the indentation.
+
+use crate::task::{self, Task, DATE_FORMAT};
+use crate::tree::{self, Tree};
+use std::fmt::Write;
+
+/// The indentation of each level of subtasks.
+const INDENT: &str = "  ";
+
+fn write_tasks(out: &mut String, tree: &Tree, idx: usize, depth: usize) {
+    for &child in tree.tasks()[idx].children() {
+        let task = &tree.tasks()[child];
+        let check = if task.is_complete() { 'x' } else { ' ' };
+
+        let _ = write!(
+            out,
+            "{}- [{}] {}",
+            INDENT.repeat(depth),
+            check,
+            task.name()
+        );
+        if let Some(due) = task.due() {
+            let _ = write!(out, " (due {})", due.format(DATE_FORMAT));
+        }
+        out.push('\n');
+
+        write_tasks(out, tree, child, depth + 1);
+    }
+}
+
+/// Renders the tasks below the task at `idx` as a checklist.
+pub fn export(tree: &Tree, idx: usize) -> String {
+    let mut out = String::new();
+    write_tasks(&mut out, tree, idx, 0);
+    out
+}
+
+/// Splits a line into its indentation and the name and status of the task
+/// in its checkbox, if it has one.
+fn parse_item(line: &str) -> Option<(usize, &str, bool)> {
+    let item = line.trim_start();
+    let indent = line[..line.len() - item.len()]
+        .chars()
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+
+    let item = match item.find(|c: char| !c.is_ascii_digit()) {
+        // An ordered list item, e.g. "1. [ ] Draft"
+        Some(digits) if digits > 0 => {
+            item[digits..].strip_prefix(['.', ')'])?
+        }
+        _ => item.strip_prefix(['-', '*', '+'])?,
+    };
+    let item = item.strip_prefix(' ')?.trim_start();
+
+    let (done, name) = match item.get(..3)? {
+        "[ ]" => (false, &item[3..]),
+        "[x]" | "[X]" => (true, &item[3..]),
+        _ => return None,
+    };
+    if !name.is_empty() && !name.starts_with(' ') {
+        return None;
+    }
+
+    Some((indent, name.trim(), done))
+}
+
+/// Turns the text of an item into a task, reading a due date written the way
+/// it is exported.
+fn task(name: &str, done: bool) -> Task {
+    let due = name
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once(" (due "))
+        .and_then(|(name, due)| Some((name, task::parse_due(due).ok()?)));
+
+    let mut task = match due {
+        Some((name, due)) => {
+            Task::new().set_name(name.to_string()).set_due(due)
+        }
+        None => Task::new().set_name(name.to_string()),
+    };
+    if done {
+        task = task.complete();
+    }
+
+    task
+}
+
+/// Reads the checklists in a Markdown document.
+pub fn import(contents: &str) -> Tree {
+    let mut tree = Tree::new();
+    // The indentation and index of each item the next one may be nested in
+    let mut open: Vec<(usize, usize)> = Vec::new();
+
+    for (indent, name, done) in contents.lines().filter_map(parse_item) {
+        while open.last().is_some_and(|&(outer, _)| outer >= indent) {
+            open.pop();
+        }
+
+        let idx = tree.tasks().len();
+        tree.set_ptr(open.last().map_or(0, |&(_, parent)| parent));
+        tree = tree::add(tree, task(name, done));
+        open.push((indent, idx));
+    }
+
+    tree.set_ptr(0);
+    tree
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::task;
+    use crate::test_util::named;
+    use crate::tree;
+
+    #[test]
+    fn nested_checklists() {
+        let due = task::parse_due("2021-03-01 05:00 pm").unwrap();
+        let mut tree = Tree::new();
+        tree = tree::add(tree, named("Write report").set_due(due));
+        tree = tree::add(tree, named("Book room"));
+        tree = tree::descend(tree, 1);
+        tree = tree::add(tree, named("Gather figures").complete());
+        tree = tree::add(tree, named("Draft"));
+
+        assert_eq!(
+            export(&tree, 0),
+            "- [ ] Write report (due 2021-03-01 05:00 pm)
+  - [x] Gather figures
+  - [ ] Draft
+- [ ] Book room
+"
+        );
+        assert_eq!(export(&tree, 1), "- [x] Gather figures\n- [ ] Draft\n");
+    }
+
+    #[test]
+    fn checklists_are_read() {
+        let notes = "# Meeting
+
+Some notes, which are not tasks.
+
+- [ ] Write report (due 2021-03-01 05:00 pm)
+    - [X] Gather figures
+    * [ ] Draft
+\t\t1. [ ] Outline
+- not a task
+- [x] Book room
+";
+        let tree = import(notes);
+        assert_eq!(
+            export(&tree, 0),
+            "- [ ] Write report (due 2021-03-01 05:00 pm)
+  - [x] Gather figures
+  - [ ] Draft
+    - [ ] Outline
+- [x] Book room
+"
+        );
+        assert_eq!(tree.ptr(), 0);
+    }
+}
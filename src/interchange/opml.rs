@@ -0,0 +1,184 @@
+//! This module reads and writes [OPML] outlines, which outliners and mind
+//! mapping tools exchange.
+//!
+//! ```xml
+//! <outline text="Write report" _due="2021-03-01T17:00:00">
+//!   <outline text="Gather figures" _complete="true"/>
+//!   <outline text="Draft"/>
+//! </outline>
+//! ```
+//!
+//! Each task is an outline nested in the outline of its parent, in the same
+//! order. OPML has no attributes for whether a task is complete or when it is
+//! due, so these are kept in `_complete` and `_due` the way other outliners
+//! keep their own attributes. Outlines read from other tools are tasks even
+//! without them.
+//!
+//! [OPML]: http://opml.org/spec2.opml
+
+use crate::task::Task;
+use crate::tree::{self, Tree};
+use crate::Result;
+use roxmltree::{Document, Node};
+use std::fmt::Write;
+use time::PrimitiveDateTime;
+
+/// The format of the `_due` attribute.
+const DUE_FORMAT: &str = "%FT%T";
+
+/// The indentation of each level of outlines.
+const INDENT: &str = "  ";
+
+/// Escapes the characters which cannot appear in an attribute as they are.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("&#10;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn write_outlines(out: &mut String, tree: &Tree, idx: usize, depth: usize) {
+    for &child in tree.tasks()[idx].children() {
+        let task = &tree.tasks()[child];
+        let indent = INDENT.repeat(depth);
+
+        let _ =
+            write!(out, "{}<outline text=\"{}\"", indent, escape(task.name()));
+        if task.is_complete() {
+            out.push_str(" _complete=\"true\"");
+        }
+        if let Some(due) = task.due() {
+            let _ = write!(out, " _due=\"{}\"", due.format(DUE_FORMAT));
+        }
+
+        if task.children().is_empty() {
+            out.push_str("/>\n");
+        } else {
+            out.push_str(">\n");
+            write_outlines(out, tree, child, depth + 1);
+            let _ = writeln!(out, "{}</outline>", indent);
+        }
+    }
+}
+
+/// Renders the tasks below the task at `idx` as an outline titled after it.
+pub fn export(tree: &Tree, idx: usize) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n",
+    );
+    let _ = writeln!(
+        out,
+        "{}<head>\n{}<title>{}</title>\n{}</head>",
+        INDENT,
+        INDENT.repeat(2),
+        escape(tree.tasks()[idx].name()),
+        INDENT
+    );
+    let _ = writeln!(out, "{}<body>", INDENT);
+    write_outlines(&mut out, tree, idx, 2);
+    let _ = writeln!(out, "{}</body>\n</opml>", INDENT);
+    out
+}
+
+fn task(outline: Node) -> Result<Task> {
+    let name = outline.attribute("text").unwrap_or_default();
+    let mut task = Task::new().set_name(name.to_string());
+
+    if let Some(due) = outline.attribute("_due") {
+        let parsed = PrimitiveDateTime::parse(due, DUE_FORMAT)
+            .map_err(|e| format!("Invalid due date '{}': {}", due, e))?;
+        task = task.set_due(parsed);
+    }
+    if outline.attribute("_complete") == Some("true") {
+        task = task.complete();
+    }
+
+    Ok(task)
+}
+
+/// Adds the outlines in `node` below the task at `parent`.
+fn add_outlines(mut tree: Tree, node: Node, parent: usize) -> Result<Tree> {
+    for outline in node.children().filter(|n| n.has_tag_name("outline")) {
+        let idx = tree.tasks().len();
+        tree.set_ptr(parent);
+        tree = tree::add(tree, task(outline)?);
+        tree = add_outlines(tree, outline, idx)?;
+    }
+
+    Ok(tree)
+}
+
+/// Reads the outlines in the body of an OPML document.
+pub fn import(contents: &str) -> Result<Tree> {
+    let document = Document::parse(contents)
+        .map_err(|e| format!("Not an OPML outline: {}", e))?;
+    let root = document.root_element();
+    if !root.has_tag_name("opml") {
+        return Err("Not an OPML outline: its root is not <opml>".into());
+    }
+    let body = root
+        .children()
+        .find(|n| n.has_tag_name("body"))
+        .ok_or("Not an OPML outline: it has no body")?;
+
+    let mut tree = add_outlines(Tree::new(), body, 0)?;
+    tree.set_ptr(0);
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::named;
+
+    #[test]
+    fn round_trip() {
+        let due = crate::task::parse_due("2021-03-01 05:00 pm").unwrap();
+        let mut tree = Tree::new();
+        tree = tree::add(tree, named("Write \"report\"").set_due(due));
+        tree = tree::add(tree, named("Book room"));
+        tree = tree::descend(tree, 1);
+        tree = tree::add(tree, named("Gather figures").complete());
+        tree = tree::add(tree, named("Draft"));
+
+        let exported = export(&tree, 1);
+        assert!(exported.contains("<title>Write &quot;report&quot;</title>"));
+        assert!(exported.contains(
+            "    <outline text=\"Gather figures\" _complete=\"true\"/>\n"
+        ));
+
+        let exported = export(&tree, 0);
+        let imported = import(&exported).unwrap();
+        assert_eq!(export(&imported, 0), exported);
+        assert_eq!(imported.tasks()[1].due(), &Some(due));
+        assert_eq!(imported.ptr(), 0);
+    }
+
+    #[test]
+    fn outlines_from_other_tools() {
+        let tree = import(
+            "<opml version=\"1.0\"><head/><body>
+  <outline text=\"Ideas\" created=\"Mon, 01 Mar 2021\">
+    <outline text=\"Garden\"><outline text=\"Beds\"/></outline>
+  </outline>
+  <outline text=\"Done\" _complete=\"true\"/>
+</body></opml>",
+        )
+        .unwrap();
+
+        assert!(tree.find("Ideas/Garden/Beds").is_some());
+        assert!(tree.tasks()[tree.find("Done").unwrap()].is_complete());
+        assert!(import("<opml><head/></opml>").is_err());
+        assert!(import("<opml>").is_err());
+        assert!(import("<rss><body/></rss>").is_err());
+    }
+}
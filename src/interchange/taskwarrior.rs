@@ -0,0 +1,181 @@
+//! This module reads the JSON written by Taskwarrior's `task export`, either
+//! as an array or, as older versions write it, an object on each line.
+//!
+//! A project, e.g. "Home.Garden", becomes a task named after each part of it
+//! with the tasks of the project below, e.g. "Home/Garden". Deleted tasks are
+//! put in the trash and completed tasks are complete. Priorities H, M and L
+//! become A, B and C. Due dates, which Taskwarrior writes in UTC, become local
+//! times.
+
+use crate::task::Task;
+use crate::tree::{self, Tree};
+use crate::Result;
+use serde::Deserialize;
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+#[derive(Deserialize)]
+struct Exported {
+    description: String,
+    #[serde(default)]
+    project: Option<String>,
+    #[serde(default)]
+    status: Option<String>,
+    /// A time in UTC, e.g. "20210301T170000Z".
+    #[serde(default)]
+    due: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    priority: Option<String>,
+}
+
+fn parse(contents: &str) -> serde_json::Result<Vec<Exported>> {
+    if contents.trim_start().starts_with('[') {
+        return serde_json::from_str(contents);
+    }
+
+    contents
+        .lines()
+        .map(|line| line.trim().trim_end_matches(','))
+        .filter(|line| !line.is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+/// Returns the offset of the local time zone at a time. It cannot be
+/// determined in some environments, e.g. while other threads are running, in
+/// which case the time stays in UTC.
+fn local_offset(at: OffsetDateTime) -> UtcOffset {
+    UtcOffset::try_local_offset_at(at).unwrap_or(UtcOffset::UTC)
+}
+
+/// Reads a due date written in UTC as the time it is at the offset `offset`
+/// returns for it.
+fn parse_due<F>(due: &str, offset: F) -> Result<PrimitiveDateTime>
+where
+    F: Fn(OffsetDateTime) -> UtcOffset,
+{
+    let utc = PrimitiveDateTime::parse(due, "%Y%m%dT%H%M%SZ")
+        .map_err(|e| format!("Invalid due date '{}': {}", due, e))?
+        .assume_utc();
+    let due = utc.to_offset(offset(utc));
+    Ok(PrimitiveDateTime::new(due.date(), due.time()))
+}
+
+fn task(exported: &Exported) -> Result<Task> {
+    let mut task = Task::new().set_name(exported.description.clone());
+
+    if let Some(due) = &exported.due {
+        task = task.set_due(parse_due(due, local_offset)?);
+    }
+    if exported.status.as_deref() == Some("completed") {
+        task = task.complete();
+    }
+    for tag in exported.tags.iter() {
+        task = task.add_tag(tag.clone());
+    }
+
+    let priority = match exported.priority.as_deref() {
+        Some("H") => Some('A'),
+        Some("M") => Some('B'),
+        Some("L") => Some('C'),
+        _ => None,
+    };
+    Ok(task.set_priority(priority))
+}
+
+/// Reads the tasks in a Taskwarrior export.
+pub fn import(contents: &str) -> Result<Tree> {
+    let exported = parse(contents)
+        .map_err(|e| format!("Not a Taskwarrior export: {}", e))?;
+    let mut tree = Tree::new();
+    let mut deleted = Vec::new();
+
+    for exported in exported.iter() {
+        let project = exported.project.as_deref().unwrap_or_default();
+        let (with_project, parent) = super::add_path(
+            tree,
+            project.split('.').map(String::from),
+            str::to_string,
+        );
+        tree = with_project;
+
+        if exported.status.as_deref() == Some("deleted") {
+            deleted.push(tree.tasks().len());
+        }
+        tree.set_ptr(parent);
+        tree = tree::add(tree, task(exported)?);
+    }
+
+    tree = tree::trash_all(tree, &deleted);
+    tree.set_ptr(0);
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::task;
+
+    #[test]
+    fn projects_and_fields() {
+        let tree = import(
+            r#"[
+{"id":1,"description":"Weed beds","project":"Home.Garden","status":"pending",
+ "priority":"H","tags":["outside"],"due":"20210301T170000Z","uuid":"a"},
+{"id":0,"description":"Mow lawn","project":"Home.Garden","status":"completed",
+ "uuid":"b"},
+{"id":0,"description":"Paint fence","project":"Home","status":"deleted",
+ "uuid":"c"},
+{"id":2,"description":"Call bank","status":"pending","priority":"L",
+ "uuid":"d"}
+]"#,
+        )
+        .unwrap();
+
+        let garden = tree.find("Home/Garden").unwrap();
+        let names: Vec<&str> = tree
+            .children_of(&tree.tasks()[garden])
+            .map(|task| task.name().as_str())
+            .collect();
+        assert_eq!(names, vec!["Weed beds", "Mow lawn"]);
+
+        let weed = &tree.tasks()[tree.find("Home/Garden/Weed beds").unwrap()];
+        assert_eq!(weed.priority(), Some('A'));
+        assert_eq!(weed.tags(), &vec![String::from("outside")]);
+        assert!(weed.due().is_some());
+        assert!(tree.tasks()[tree.find("Home/Garden/Mow lawn").unwrap()]
+            .is_complete());
+        assert_eq!(
+            tree.tasks()[tree.find("Call bank").unwrap()].priority(),
+            Some('C')
+        );
+
+        let trash = tree.trash().unwrap();
+        let fence = tree.tasks()[trash].children()[0];
+        assert_eq!(tree.tasks()[fence].name(), "Paint fence");
+        assert_eq!(tree.tasks()[fence].trashed_from(), tree.find("Home"));
+    }
+
+    #[test]
+    fn one_task_per_line() {
+        let tree = import(
+            "{\"description\":\"a\",\"status\":\"pending\"},
+{\"description\":\"b\",\"status\":\"pending\"}
+",
+        )
+        .unwrap();
+        assert_eq!(tree.children().count(), 2);
+        assert!(import("not json").is_err());
+    }
+
+    #[test]
+    fn due_dates_are_converted() {
+        let due = parse_due("20210301T170000Z", |_| UtcOffset::hours(-5));
+        assert_eq!(
+            due.unwrap(),
+            task::parse_due("2021-03-01 12:00 pm").unwrap()
+        );
+        assert!(parse_due("2021-03-01", local_offset).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! This module reads and writes the [todo.txt] format, one task per line.
+//!
+//! ```text
+//! (A) Draft +Work/Write_report @office due:2021-03-01
+//! x Gather figures +Work/Write_report pri:B
+//! ```
+//!
+//! A task's project is the path of the tasks above it, so the tasks of a
+//! project become subtasks of a task of the same name, and every task is
+//! written with its ancestors as its project. Since a project cannot contain
+//! spaces, spaces and slashes in names are written as underscores, and a
+//! project is matched to the tasks above it in the same form. Contexts become
+//! tags, while any projects after the first are kept as tags starting with
+//! '+'. Due dates are written without their time.
+//!
+//! [todo.txt]: https://github.com/todotxt/todo.txt
+
+use crate::task::{self, Task};
+use crate::tree::{self, Tree};
+use time::Date;
+
+fn write_tasks(
+    out: &mut String,
+    tree: &Tree,
+    idx: usize,
+    path: &mut Vec<String>,
+) {
+    for &child in tree.tasks()[idx].children() {
+        let task = &tree.tasks()[child];
+        out.push_str(&line(task, path));
+        out.push('\n');
+
+        path.push(project_name(task.name()));
+        write_tasks(out, tree, child, path);
+        path.pop();
+    }
+}
+
+fn line(task: &Task, path: &[String]) -> String {
+    let mut words = Vec::new();
+    if task.is_complete() {
+        words.push(String::from("x"));
+    } else if let Some(priority) = task.priority() {
+        words.push(format!("({})", priority));
+    }
+
+    words.push(task.name().clone());
+    if !path.is_empty() {
+        words.push(format!("+{}", path.join("/")));
+    }
+    for tag in task.tags() {
+        let tag = tag.replace(' ', "_");
+        if tag.starts_with('+') {
+            words.push(tag);
+        } else {
+            words.push(format!("@{}", tag));
+        }
+    }
+    if let Some(due) = task.due() {
+        words.push(format!("due:{}", due.format("%F")));
+    }
+    // A completed task keeps its priority as a key
+    if let (true, Some(priority)) = (task.is_complete(), task.priority()) {
+        words.push(format!("pri:{}", priority));
+    }
+
+    words.join(" ")
+}
+
+/// Writes every task below the task at `idx` on a line of its own.
+pub fn export(tree: &Tree, idx: usize) -> String {
+    let mut out = String::new();
+    write_tasks(&mut out, tree, idx, &mut Vec::new());
+    out
+}
+
+/// Writes a name the way it appears in a project.
+fn project_name(name: &str) -> String {
+    name.replace([' ', '/'], "_")
+}
+
+fn is_date(word: &str) -> bool {
+    Date::parse(word, "%F").is_ok()
+}
+
+/// Parses a priority written as "(A)", or as "A" in a "pri:" key.
+fn priority(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_uppercase() => Some(c),
+        _ => None,
+    }
+}
+
+/// Reads a task and the project it belongs to from a line.
+fn parse_line(line: &str) -> (Task, Option<String>) {
+    let mut words = line.split_whitespace().peekable();
+    let mut task = Task::new();
+
+    let done = words.next_if_eq(&"x").is_some();
+    if done {
+        task = task.complete();
+        // The dates it was completed and created on
+        words.next_if(|word| is_date(word));
+    } else {
+        let pri = words.next_if(|word| {
+            word.strip_prefix('(')
+                .and_then(|word| word.strip_suffix(')'))
+                .and_then(priority)
+                .is_some()
+        });
+        task = task.set_priority(pri.and_then(|pri| priority(&pri[1..2])));
+    }
+    words.next_if(|word| is_date(word));
+
+    let mut name = Vec::new();
+    let mut project = None;
+    for word in words {
+        if let Some(tag) = word.strip_prefix('@').filter(|tag| !tag.is_empty())
+        {
+            task = task.add_tag(tag.to_string());
+        } else if word.len() > 1 && word.starts_with('+') {
+            if project.is_none() {
+                project = Some(word[1..].to_string());
+            } else {
+                task = task.add_tag(word.to_string());
+            }
+        } else if let Some(due) = word
+            .strip_prefix("due:")
+            .and_then(|due| task::parse_due(due).ok())
+        {
+            task = task.set_due(due);
+        } else if let Some(pri) = word.strip_prefix("pri:").and_then(priority) {
+            task = task.set_priority(Some(pri));
+        } else {
+            name.push(word);
+        }
+    }
+
+    (task.set_name(name.join(" ")), project)
+}
+
+/// Reads the tasks in a todo.txt file.
+pub fn import(contents: &str) -> Tree {
+    let mut tree = Tree::new();
+
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        let (task, parent) = parse_line(line);
+        let (with_parent, parent) = match parent {
+            Some(parent) => super::add_path(
+                tree,
+                parent.split('/').map(|name| name.replace('_', " ")),
+                project_name,
+            ),
+            None => (tree, 0),
+        };
+
+        tree = with_parent;
+        tree.set_ptr(parent);
+        tree = tree::add(tree, task);
+    }
+
+    tree.set_ptr(0);
+    tree
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::named;
+
+    #[test]
+    fn projects_are_paths() {
+        let due = task::parse_due("2021-03-01").unwrap();
+        let mut tree = Tree::new();
+        tree = tree::add(tree, named("Work"));
+        tree.set_ptr(1);
+        tree = tree::add(tree, named("Write report"));
+        tree.set_ptr(2);
+        tree = tree::add(
+            tree,
+            named("Draft")
+                .set_priority(Some('A'))
+                .add_tag(String::from("office"))
+                .set_due(due),
+        );
+        tree = tree::add(tree, named("Gather figures").set_priority(Some('B')));
+        tree = tree::complete(tree, 4);
+        // Underscores and slashes in a name are not read back as spaces
+        tree.set_ptr(0);
+        tree = tree::add(tree, named("snake_case and/or"));
+        tree.set_ptr(5);
+        tree = tree::add(tree, named("Sub"));
+
+        let exported = export(&tree, 0);
+        assert_eq!(
+            exported,
+            "Work
+Write report +Work
+(A) Draft +Work/Write_report @office due:2021-03-01
+x Gather figures +Work/Write_report pri:B
+snake_case and/or
+Sub +snake_case_and_or
+"
+        );
+        assert_eq!(export(&import(&exported), 0), exported);
+    }
+
+    #[test]
+    fn lines_are_read() {
+        let tree = import(
+            "x 2021-03-02 2021-02-01 Call Bob +Phone +Family @home
+(C) 2021-02-01 Buy milk +Errands due:2021-03-01
+
+Unfiled task
+",
+        );
+
+        let phone = tree.find("Phone").unwrap();
+        let call = &tree.tasks()[tree.tasks()[phone].children()[0]];
+        assert_eq!(call.name(), "Call Bob");
+        assert!(call.is_complete());
+        assert_eq!(
+            call.tags(),
+            &vec![String::from("+Family"), String::from("home")]
+        );
+
+        let milk = tree.find("Errands/Buy milk").unwrap();
+        assert_eq!(tree.tasks()[milk].priority(), Some('C'));
+        assert!(tree.tasks()[milk].due().is_some());
+        assert!(tree.find("Unfiled task").is_some());
+    }
+}
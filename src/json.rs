@@ -0,0 +1,128 @@
+//! This module describes tasks as JSON for scripts, e.g. for `toru list
+//! --json`, so that they need not scrape the listing meant to be read.
+//!
+//! ```json
+//! {"task":{"id":6713408829207519601,"name":"Work","path":"Work",
+//!  "status":"pending","due":null,"tags":[],"priority":null,
+//!  "children":[1528290350141702357]},
+//!  "children":[{"id":1528290350141702357,..}]}
+//! ```
+//!
+//! A task's id is its uid, which stays the same when it is renamed or moved,
+//! so a script can tell it apart from one run to the next. Its path is the
+//! one `--at` takes.
+
+use crate::tree::Tree;
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Task<'a> {
+    id: u64,
+    name: &'a str,
+    path: String,
+    status: &'static str,
+    /// Written as e.g. "2021-03-01T17:00:00".
+    due: Option<String>,
+    tags: &'a [String],
+    priority: Option<char>,
+    children: Vec<u64>,
+}
+
+#[derive(Serialize)]
+struct Listing<'a> {
+    task: Task<'a>,
+    children: Vec<Task<'a>>,
+}
+
+/// Returns the names of the tasks leading to a task from the root, separated
+/// by slashes. Tasks in the trash start with the name of the trash.
+fn path(tree: &Tree, idx: usize) -> String {
+    let names: Vec<&str> = tree
+        .ancestry(idx)
+        .into_iter()
+        .filter(|&ancestor| ancestor != 0)
+        .map(|ancestor| tree.tasks()[ancestor].name().as_str())
+        .collect();
+    names.join("/")
+}
+
+fn task(tree: &Tree, idx: usize) -> Task<'_> {
+    let task = &tree.tasks()[idx];
+
+    Task {
+        id: tree.uid(idx),
+        name: task.name(),
+        path: path(tree, idx),
+        status: if task.is_complete() {
+            "complete"
+        } else {
+            "pending"
+        },
+        due: task.due().map(|due| due.format("%FT%T")),
+        tags: task.tags(),
+        priority: task.priority(),
+        children: task
+            .children()
+            .iter()
+            .map(|&child| tree.uid(child))
+            .collect(),
+    }
+}
+
+/// Describes the task at `idx` along with all of its children, whether they
+/// are complete or not.
+pub fn list(tree: &Tree, idx: usize) -> serde_json::Result<String> {
+    let listing = Listing {
+        task: task(tree, idx),
+        children: tree.tasks()[idx]
+            .children()
+            .iter()
+            .map(|&child| task(tree, child))
+            .collect(),
+    };
+
+    serde_json::to_string(&listing)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::task;
+    use crate::tree;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn listing_fields() {
+        let due = task::parse_due("2021-03-01 05:00 pm").unwrap();
+        let mut tree = Tree::new();
+        tree =
+            tree::add(tree, task::Task::new().set_name(String::from("Work")));
+        tree.set_ptr(1);
+        tree = tree::add(
+            tree,
+            task::Task::new()
+                .set_name(String::from("Report"))
+                .set_due(due),
+        );
+        tree = tree::complete(tree, 2);
+
+        let listing: Value =
+            serde_json::from_str(&list(&tree, 1).unwrap()).unwrap();
+        assert_eq!(listing["task"]["path"], "Work");
+        assert_eq!(listing["task"]["id"], tree.uid(1));
+        assert_eq!(listing["task"]["children"], json!([tree.uid(2)]));
+        assert_eq!(
+            listing["children"][0],
+            json!({
+                "id": tree.uid(2),
+                "name": "Report",
+                "path": "Work/Report",
+                "status": "complete",
+                "due": "2021-03-01T17:00:00",
+                "tags": [],
+                "priority": null,
+                "children": [],
+            })
+        );
+    }
+}
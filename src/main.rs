@@ -1,56 +1,277 @@
+mod args;
 mod cli;
+mod config;
+mod fsck;
+mod interchange;
+mod json;
+mod nested;
+mod schema;
+mod storage;
 mod task;
+#[cfg(test)]
+mod test_util;
 mod tree;
 mod tui;
 
+use args::Mode;
 use cli::CLI;
-use std::{env, error::Error, fmt, fs::File, path::Path};
-use tree::Tree;
+use interchange::Interchange;
+use std::{
+    env,
+    error::Error,
+    fmt, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process,
+};
+use storage::{Storage, TaskFile};
 
 type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
-fn main() -> Result<()> {
-    let file_name = Path::new(".toru.yaml");
-    let key = if cfg!(windows) { "HOMEPATH" } else { "HOME" };
-
-    let path = match env::var(key) {
-        Ok(home) => Path::new(&home).join(file_name),
-        Err(_) => file_name.to_path_buf(),
-    };
-
-    let file = File::open(&path);
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
 
-    let mut tree = if let Ok(file) = file {
-        serde_yaml::from_reader::<_, Tree>(file).unwrap()
+fn run() -> Result<()> {
+    let args = args::parse(env::args().skip(1))?;
+    let path =
+        storage::resolve_path(args.file, args.list.as_deref(), args.global)?;
+    // Held until toru exits so that no other instance edits the file meanwhile.
+    // Listing and exporting only read the file, which is never left half
+    // written, so they do not wait for an instance that is editing it.
+    let read_only = matches!(args.mode, Mode::List | Mode::Export(_));
+    let _lock = if read_only {
+        None
     } else {
-        Tree::new()
+        Some(storage::lock(&path)?)
     };
+    let yaml = !storage::is_database(&path) && !storage::is_log(&path);
+    if matches!(args.mode, Mode::Convert(_)) && !yaml {
+        return Err(ToruError::InvalidArgument(format!(
+            "{} is not a YAML task file, use 'toru export' to copy its tasks \
+             to one",
+            path.display()
+        ))
+        .into());
+    }
+    let mut file = storage::open(path)?;
 
-    if let Some(value) = env::args().nth(1) {
-        if value == "-i" {
-            CLI::default().run()?;
-        } else if value == "-s" {
+    if args.mode == Mode::Fsck {
+        return fsck::run(file.as_mut());
+    }
+    let tree = load_or_recover(&mut file)?;
+
+    match args.mode {
+        Mode::Cli => CLI::new(file, tree).set_json(args.json).run()?,
+        Mode::Server => {
             // Server branch
-        } else {
-            Err(Box::new(ToruError::InstantiateError))?;
         }
-    } else if cfg!(windows) {
-        CLI::default().run()?;
-    } else {
-        tree = tui::run(tree)?;
+        Mode::Default if cfg!(windows) || !termion::is_tty(&io::stdout()) => {
+            // Without a terminal to draw on fall back to the line based
+            // interface
+            CLI::new(file, tree).run()?;
+        }
+        Mode::Default => {
+            let keymap = config::load()?.keymap()?;
+            tui::run(tree, keymap, file)?;
+        }
+        Mode::Convert(format) => {
+            let mut file = TaskFile::new(file.path().to_path_buf());
+            file.set_format(format);
+            file.save(&tree)?;
+            println!("{} is now {}", file.path().display(), format);
+        }
+        Mode::Export(path) => {
+            export(&tree, file.path(), path, args.format, args.at)?
+        }
+        Mode::Import(path) => {
+            let tree = import(tree, path, args.format, args.at)?;
+            file.save(&tree)?;
+        }
+        Mode::List => {
+            let idx = locate(&tree, args.at.as_deref(), tree.ptr())?;
+            if args.json {
+                println!("{}", json::list(&tree, idx)?);
+            } else {
+                let mut tree = tree;
+                tree.set_ptr(idx);
+                cli::list(&mut cli::IO::new(), &tree);
+            }
+        }
+        Mode::Fsck => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Finds the task named by `--at`, or the task at `default` when there is no
+/// `--at`.
+fn locate(
+    tree: &tree::Tree,
+    at: Option<&str>,
+    default: usize,
+) -> Result<usize> {
+    match at {
+        Some(at) => Ok(tree
+            .find(at)
+            .ok_or_else(|| format!("There is no task at '{}'", at))?),
+        None => Ok(default),
     }
+}
 
-    let file = File::create(&path)?;
-    serde_yaml::to_writer(file, &tree)?;
+/// Copies the tree to a new task file, or writes the tasks below the task at
+/// `at` in another format to a file or the terminal.
+fn export(
+    tree: &tree::Tree,
+    from: &Path,
+    path: Option<PathBuf>,
+    format: Option<Interchange>,
+    at: Option<String>,
+) -> Result<()> {
+    let format =
+        format.or_else(|| path.as_deref().and_then(Interchange::detect));
+    let (path, format) = match (path, format) {
+        (Some(path), None) => {
+            // Unlike a file in another format, which is exported again and
+            // again, a task file is never overwritten
+            if path.exists() {
+                return Err(format!(
+                    "{} already exists, export to a new file",
+                    path.display()
+                )
+                .into());
+            }
+
+            storage::open(path.clone())?.save(tree)?;
+            println!("Exported {} to {}", from.display(), path.display());
+            return Ok(());
+        }
+        (path, Some(format)) => (path, format),
+        // The arguments name a path or a format
+        (None, None) => unreachable!(),
+    };
+
+    let idx = locate(tree, at.as_deref(), 0)?;
+    let contents = interchange::export(tree, idx, format)?;
+
+    match path {
+        Some(path) => fs::write(path, contents)?,
+        None => print!("{}", contents),
+    }
 
     Ok(())
 }
 
+/// Adds the tasks of another task file, or the tasks read from a file or
+/// standard input in another format, under the task at `at` or the current
+/// task.
+fn import(
+    tree: tree::Tree,
+    path: Option<PathBuf>,
+    format: Option<Interchange>,
+    at: Option<String>,
+) -> Result<tree::Tree> {
+    let under = locate(&tree, at.as_deref(), tree.ptr())?;
+
+    let format =
+        format.or_else(|| path.as_deref().and_then(Interchange::detect));
+    let (other, source) = match (path, format) {
+        (Some(path), None) => {
+            let other = storage::open(path.clone())?.load()?;
+            (other, path.display().to_string())
+        }
+        (Some(path), Some(format)) => {
+            let contents = fs::read_to_string(&path)?;
+            let other = interchange::import(&contents, format)?;
+            (other, path.display().to_string())
+        }
+        (None, Some(format)) => {
+            let mut contents = String::new();
+            io::stdin().read_to_string(&mut contents)?;
+            let other = interchange::import(&contents, format)?;
+            (other, String::from("standard input"))
+        }
+        // The arguments name a path or a format
+        (None, None) => unreachable!(),
+    };
+
+    let before = tree.tasks().len();
+    let had_trash = tree.trash().is_some();
+    let mut tree = tree::graft(tree, &other, under);
+    // Tasks deleted in another tool are kept in the trash, unlike the trash
+    // of another task file
+    if format.is_some() {
+        tree = tree::graft_trash(tree, &other, under);
+    }
+    println!(
+        "Imported {} task(s) from {}",
+        // The trash itself is not an imported task
+        tree.tasks().len()
+            - before
+            - usize::from(!had_trash && tree.trash().is_some()),
+        source
+    );
+
+    Ok(tree)
+}
+
+/// Loads the task file, offering to open its latest backup instead when it
+/// cannot be loaded and someone is at the terminal to answer.
+fn load_or_recover(file: &mut Box<dyn Storage>) -> Result<tree::Tree> {
+    let error = match file.load() {
+        Ok(tree) => return Ok(tree),
+        Err(e) => e,
+    };
+
+    let backup = match file.backup() {
+        Some(backup) if backup.exists() && termion::is_tty(&io::stdin()) => {
+            backup
+        }
+        _ => return Err(error),
+    };
+
+    eprintln!("{}", error);
+    eprint!(
+        "Open the latest backup, {}, instead? [y/N] ",
+        backup.display()
+    );
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        return Err(error);
+    }
+
+    let mut backup = TaskFile::new(backup);
+    let tree = backup.load()?;
+    let mut recovered = TaskFile::new(file.path().to_path_buf());
+    recovered.set_format(backup.format());
+    *file = Box::new(recovered);
+    eprintln!(
+        "The damaged file becomes the latest backup the next time tasks are \
+         saved"
+    );
+    Ok(tree)
+}
+
 #[derive(Debug)]
 pub enum ToruError {
+    ConfigError(String),
+    /// The task file is not a valid tree, listing everything wrong with it.
+    DamagedTree(PathBuf, Vec<String>),
     IoError,
-    InstantiateError,
+    InvalidArgument(String),
     InvalidIndex(usize),
+    /// The task file could not be parsed. The location is a line and column.
+    LoadError {
+        path: PathBuf,
+        location: Option<(usize, usize)>,
+        message: String,
+    },
     ParseCommandFailure,
 }
 
@@ -59,13 +280,40 @@ impl Error for ToruError {}
 impl fmt::Display for ToruError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let msg = match self {
+            Self::ConfigError(msg) => format!("Invalid config: {}", msg),
             Self::IoError => String::from("Error in IO operations"),
-            Self::InstantiateError => {
-                format!("Error creating an instance of toru")
+            Self::InvalidArgument(msg) => format!("{}\n\n{}", msg, args::USAGE),
+            Self::DamagedTree(path, problems) => {
+                let more = match problems.len() {
+                    0 | 1 => String::new(),
+                    n => format!(" (and {} more problems)", n - 1),
+                };
+                format!(
+                    "{} is damaged: {}{}. Run 'toru fsck' to repair it",
+                    path.display(),
+                    problems.first().map_or("", |p| p.as_str()),
+                    more
+                )
             }
             Self::InvalidIndex(idx) => {
                 format!("Child at index {} does not exist", idx)
             }
+            Self::LoadError {
+                path,
+                location: Some((line, column)),
+                message,
+            } => format!(
+                "Failed to load {}, line {} column {}: {}",
+                path.display(),
+                line,
+                column,
+                message
+            ),
+            Self::LoadError {
+                path,
+                location: None,
+                message,
+            } => format!("Failed to load {}: {}", path.display(), message),
             Self::ParseCommandFailure => {
                 String::from("Failed to parse command")
             }
@@ -0,0 +1,286 @@
+//! This module converts a [`Tree`] to and from the nested format of the task
+//! file. Instead of a flat list of tasks linked by index, every task is
+//! written under its parent, which makes the file easy to edit by hand and to
+//! review in a diff.
+//!
+//! ```yaml
+//! version: 1
+//! format: nested
+//! current: [0]
+//! tasks:
+//!   - name: Write report
+//!     due: 2021-03-01 05:00 pm
+//!     tags: [work]
+//!     tasks:
+//!       - name: Gather figures
+//!         done: true
+//!       - name: Draft
+//! trash:
+//!   - name: Old idea
+//!     from: [0]
+//! ```
+//!
+//! The current task and the task a trashed task was deleted from are written
+//! as the positions of the tasks leading to them from the root, e.g. `[0, 1]`
+//! is "Draft" above. toru also writes the `uid` of each task, which tasks
+//! added by hand can leave out.
+//!
+//! [`Tree`]: ../tree/struct.Tree.html
+
+use crate::storage::Format;
+use crate::task::Task;
+use crate::tree::{self, Tree};
+use serde::{Deserialize, Serialize};
+use time::PrimitiveDateTime;
+
+#[derive(Serialize, Deserialize)]
+pub struct Document {
+    format: Format,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    current: Vec<usize>,
+    #[serde(default)]
+    tasks: Vec<Entry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trash: Option<Vec<Entry>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "due")]
+    due: Option<PrimitiveDateTime>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    done: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<char>,
+    /// The position of the task this one was deleted from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    from: Option<Vec<usize>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uid: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tasks: Vec<Entry>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Due dates are written the way they are entered rather than as the fields
+/// of a date.
+mod due {
+    use crate::task::{self, DATE_FORMAT};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use time::PrimitiveDateTime;
+
+    pub fn serialize<S: Serializer>(
+        due: &Option<PrimitiveDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match due {
+            Some(due) => serializer.serialize_str(&due.format(DATE_FORMAT)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<PrimitiveDateTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| task::parse_due(&s).map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Returns the positions leading from the root to a task, or `None` for the
+/// trash and the tasks in it.
+fn position(tree: &Tree, idx: usize) -> Option<Vec<usize>> {
+    let ancestry = tree.ancestry(idx);
+    if ancestry.first() != Some(&0) {
+        return None;
+    }
+
+    ancestry
+        .windows(2)
+        .map(|pair| {
+            let parent = tree.task(pair[0])?;
+            parent.children().iter().position(|&child| child == pair[1])
+        })
+        .collect()
+}
+
+/// Finds the task at a position written by [`position`].
+///
+/// [`position`]: ./fn.position.html
+fn find(tree: &Tree, position: &[usize]) -> Option<usize> {
+    let mut idx = 0;
+    for &n in position {
+        idx = *tree.task(idx)?.children().get(n)?;
+    }
+
+    Some(idx)
+}
+
+fn entry(tree: &Tree, idx: usize) -> Entry {
+    let task = &tree.tasks()[idx];
+
+    Entry {
+        name: task.name().clone(),
+        due: *task.due(),
+        done: task.is_complete(),
+        tags: task.tags().clone(),
+        priority: task.priority(),
+        from: task.trashed_from().and_then(|from| position(tree, from)),
+        uid: task.uid(),
+        tasks: task
+            .children()
+            .iter()
+            .map(|&child| entry(tree, child))
+            .collect(),
+    }
+}
+
+/// Lays out a tree in the nested format.
+pub fn from_tree(tree: &Tree) -> Document {
+    let entries = |idx: usize| -> Vec<Entry> {
+        tree.tasks()[idx]
+            .children()
+            .iter()
+            .map(|&child| entry(tree, child))
+            .collect()
+    };
+
+    Document {
+        format: Format::Nested,
+        // A current task in the trash is not kept
+        current: position(tree, tree.ptr()).unwrap_or_default(),
+        tasks: entries(0),
+        trash: tree.trash().map(entries),
+    }
+}
+
+/// Adds a task and its subtasks under `parent`, noting where the trashed ones
+/// came from so that it can be looked up once every task is in place.
+fn add(
+    mut tree: Tree,
+    entry: Entry,
+    parent: usize,
+    trashed: &mut Vec<(usize, Vec<usize>)>,
+) -> Tree {
+    let mut task = Task::new()
+        .set_name(entry.name)
+        .set_priority(entry.priority);
+    if let Some(due) = entry.due {
+        task = task.set_due(due);
+    }
+    if entry.done {
+        task = task.complete();
+    }
+    for tag in entry.tags {
+        task = task.add_tag(tag);
+    }
+
+    let idx = tree.tasks().len();
+    tree.set_ptr(parent);
+    tree = tree::add(tree, task);
+    // A task written without a uid is given one when the tree is loaded
+    tree.tasks_mut()[idx] = tree.tasks()[idx].clone().set_uid(entry.uid);
+
+    if let Some(from) = entry.from {
+        trashed.push((idx, from));
+    }
+    for child in entry.tasks {
+        tree = add(tree, child, idx, trashed);
+    }
+
+    tree
+}
+
+/// Builds the tree laid out in a document. Positions which do not lead to a
+/// task are ignored.
+pub fn into_tree(document: Document) -> Tree {
+    let mut tree = Tree::new();
+    let mut trashed = Vec::new();
+
+    for entry in document.tasks {
+        tree = add(tree, entry, 0, &mut trashed);
+    }
+
+    if let Some(entries) = document.trash {
+        let trash = tree.tasks().len();
+        tree.tasks_mut()
+            .push(Task::new().set_name(String::from("Trash")));
+        tree.set_trash(Some(trash));
+
+        for entry in entries {
+            tree = add(tree, entry, trash, &mut trashed);
+        }
+    }
+
+    for (idx, from) in trashed {
+        if let Some(from) = find(&tree, &from) {
+            let task = tree.tasks()[idx].clone().set_trashed_from(Some(from));
+            tree = tree.replace_task(idx, task);
+        }
+    }
+
+    let ptr = find(&tree, &document.current).unwrap_or(0);
+    tree.set_ptr(ptr);
+    tree
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fsck;
+    use crate::test_util::named;
+
+    #[test]
+    fn round_trip() {
+        let mut tree = Tree::new();
+        tree = tree::add(tree, named("report").add_tag(String::from("work")));
+        tree = tree::add(tree, named("idea"));
+        tree = tree::descend(tree, 1);
+        tree = tree::add(tree, named("figures"));
+        tree = tree::add(tree, named("draft"));
+        tree = tree::complete(tree, 3);
+        tree = tree::trash(tree, 2);
+        tree.set_ptr(4);
+
+        let yaml = serde_yaml::to_string(&from_tree(&tree)).unwrap();
+        assert!(!yaml.contains("parent"));
+
+        let document: Document = serde_yaml::from_str(&yaml).unwrap();
+        let loaded = into_tree(document);
+        assert!(fsck::check(&loaded).is_empty());
+
+        let names = |tree: &Tree, idx: usize| -> Vec<String> {
+            tree.children_of(&tree.tasks()[idx])
+                .map(|task| task.name().clone())
+                .collect()
+        };
+        assert_eq!(names(&loaded, 0), vec!["report"]);
+        assert_eq!(names(&loaded, 1), vec!["figures", "draft"]);
+        assert_eq!(loaded.current().name(), "draft");
+        assert_eq!(loaded.tasks()[1].tags(), &vec![String::from("work")]);
+        assert!(loaded.tasks()[2].is_complete());
+
+        let trash = loaded.trash().unwrap();
+        let idea = loaded.tasks()[trash].children()[0];
+        assert_eq!(loaded.tasks()[idea].trashed_from(), Some(0));
+    }
+
+    #[test]
+    fn due_dates_are_readable() {
+        let due = crate::task::parse_due("2021-03-01 05:00 PM").unwrap();
+        let tree = tree::add(Tree::new(), named("report").set_due(due));
+
+        let yaml = serde_yaml::to_string(&from_tree(&tree)).unwrap();
+        assert!(yaml.contains("2021-03-01 05:00 pm"));
+
+        let loaded = into_tree(serde_yaml::from_str(&yaml).unwrap());
+        assert_eq!(*loaded.tasks()[1].due(), Some(due));
+    }
+}
@@ -0,0 +1,116 @@
+//! This module versions the format of the task file. Every file records the
+//! version of the format it was written in, and a file in an older format is
+//! upgraded by a chain of migrations as it is loaded. Files from before the
+//! format was versioned have no version and count as version 0.
+//!
+//! A new field does not need a new version as long as it has a default for
+//! files that lack it. A new version and a migration are only needed when the
+//! data already in files has to be rewritten.
+
+use serde::Serialize;
+use serde_yaml::Value;
+
+/// The version of the format this build of toru reads and writes.
+pub const VERSION: u64 = 1;
+
+/// Upgrades a file by one version. The first migration upgrades version 0 to
+/// version 1, the second version 1 to version 2 and so on.
+type Migration = fn(Value) -> Result<Value, String>;
+
+const MIGRATIONS: [Migration; VERSION as usize] = [add_version];
+
+/// Version 1 is version 0 with the version recorded, which happens as the
+/// file is written.
+fn add_version(value: Value) -> Result<Value, String> {
+    Ok(value)
+}
+
+/// A tree, in either format, along with the version of the format as it is
+/// written to disk.
+#[derive(Serialize)]
+pub struct Versioned<'a, T> {
+    version: u64,
+    #[serde(flatten)]
+    tree: &'a T,
+}
+
+pub fn versioned<T>(tree: &T) -> Versioned<'_, T> {
+    Versioned {
+        version: VERSION,
+        tree,
+    }
+}
+
+/// Returns the version of the format a parsed file was written in.
+pub fn version(value: &Value) -> Result<u64, String> {
+    let mapping = match value {
+        Value::Mapping(mapping) => mapping,
+        _ => return Err(String::from("expected a mapping of tree fields")),
+    };
+
+    let version = match mapping.get(&Value::from("version")) {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| String::from("the version is not a number"))?,
+    };
+
+    if version > VERSION {
+        return Err(format!(
+            "written in format version {} by a newer toru, this one reads up \
+             to version {}",
+            version, VERSION
+        ));
+    }
+
+    Ok(version)
+}
+
+/// Upgrades a parsed file from the version it was written in to the current
+/// one.
+pub fn migrate(mut value: Value, from: u64) -> Result<Value, String> {
+    for migration in MIGRATIONS.iter().skip(from as usize) {
+        value = migration(value)?;
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tree::Tree;
+
+    #[test]
+    fn unversioned_files_are_migrated() {
+        let old = "
+ptr: 0
+tasks:
+  - parent: ~
+    name: Root
+    due: ~
+    status: Pending
+    children: [1]
+  - parent: 0
+    name: a
+    children: []
+";
+        let value: Value = serde_yaml::from_str(old).unwrap();
+        assert_eq!(version(&value), Ok(0));
+
+        let tree: Tree =
+            serde_yaml::from_value(migrate(value, 0).unwrap()).unwrap();
+        assert_eq!(tree.children().next().unwrap().name(), "a");
+
+        let written = serde_yaml::to_string(&versioned(&tree)).unwrap();
+        let value: Value = serde_yaml::from_str(&written).unwrap();
+        assert_eq!(version(&value), Ok(VERSION));
+    }
+
+    #[test]
+    fn newer_files_are_rejected() {
+        let value: Value = serde_yaml::from_str("version: 99").unwrap();
+        assert!(version(&value).is_err());
+        assert!(version(&Value::from("tasks")).is_err());
+    }
+}
@@ -0,0 +1,734 @@
+//! This module finds, reads and writes the task file.
+//!
+//! The task file is the one given with `--file`, the named list given with
+//! `--list`, or the file in `TORU_FILE`, in that order. Otherwise toru looks
+//! for a project's tasks in the working directory and each directory above
+//! it, the way git finds `.git`. A project keeps its tasks in `.toru.yaml`, or
+//! in `.toru/tasks.yaml` to keep the backups and lock file out of sight. When
+//! there is no project, or `--global` is given, the task file is
+//! `~/.toru.yaml` if that exists, as it did before named lists, and the
+//! default list in the data directory if not. Named lists live in
+//! `$XDG_DATA_HOME/toru`, or `~/.local/share/toru` when `XDG_DATA_HOME` is not
+//! set, e.g. `toru --list work` uses `~/.local/share/toru/work.yaml`.
+//!
+//! Saving never truncates the task file in place. The tree is written to a
+//! temporary file in the same directory, synced to disk and then renamed over
+//! the original, so a crash leaves either the old or the new file behind. A
+//! symlinked task file is replaced where the link points, keeping its
+//! permissions. The previous versions are kept as `.toru.yaml.1` (the most
+//! recent) up to `.toru.yaml.5`. A file is backed up the first time toru saves
+//! it rather than on every save, so a session of autosaves only takes up one
+//! backup.
+//!
+//! A task file is written in one of two [`Format`]s. The flat format lists
+//! every task one after another, linked to each other by index, while the
+//! nested format writes tasks under their parents. A file keeps the format it
+//! is in whenever it is saved, and `toru convert FORMAT` switches formats.
+//!
+//! A task file whose name ends in `.db`, `.sqlite` or `.sqlite3` is an SQLite
+//! database instead, which saves only the tasks that changed, and one ending
+//! in `.jsonl` is a log of every change made to the tasks. Each kind of file
+//! is a [`Storage`], and `toru export` and `toru import` copy tasks from one
+//! to another.
+//!
+//! A task file is only edited by one instance of toru at a time. The instance
+//! holds an advisory [`Lock`] on `.toru.yaml.lock` and any other instance waits
+//! for it to be released before loading the file. `toru list` and `toru
+//! export` only read the file, so they take no lock and never wait.
+//!
+//! [`Format`]: ./enum.Format.html
+//! [`Lock`]: ./struct.Lock.html
+//! [`Storage`]: ./trait.Storage.html
+
+mod log;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use crate::fsck;
+use crate::nested::{self, Document};
+use crate::schema;
+use crate::tree::{self, Tree};
+use crate::{Result, ToruError};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_yaml::Value;
+use std::{
+    env,
+    ffi::OsString,
+    fmt,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
+
+/// The number of previous versions of the task file which are kept.
+pub const BACKUP_COUNT: usize = 5;
+
+/// The name of the list used when no other task file is given.
+const DEFAULT_LIST: &str = "tasks";
+
+fn home_dir() -> Option<PathBuf> {
+    let key = if cfg!(windows) { "HOMEPATH" } else { "HOME" };
+    env::var(key).ok().map(PathBuf::from)
+}
+
+/// Returns the directory named lists are kept in.
+pub fn data_dir() -> Option<PathBuf> {
+    let directory = match env::var("XDG_DATA_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir()?.join(".local").join("share"),
+    };
+
+    Some(directory.join("toru"))
+}
+
+/// Returns the path of a named list in the data directory.
+pub fn list_path(name: &str) -> Result<PathBuf> {
+    let is_plain = !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\']);
+    if !is_plain {
+        return Err(ToruError::InvalidArgument(format!(
+            "'{}' is not a valid list name",
+            name
+        ))
+        .into());
+    }
+
+    match data_dir() {
+        Some(dir) => Ok(dir.join(format!("{}.yaml", name))),
+        None => Err("No data directory, set XDG_DATA_HOME or HOME".into()),
+    }
+}
+
+/// Returns the task file of the project `start` is in, if any. The closest
+/// `.toru.yaml` or `.toru` directory wins, and `.toru.yaml` is preferred when
+/// a directory has both.
+pub fn discover(start: &Path) -> Option<PathBuf> {
+    for directory in start.ancestors() {
+        let file = directory.join(".toru.yaml");
+        if file.is_file() {
+            return Some(file);
+        }
+
+        let project = directory.join(".toru");
+        if project.is_dir() {
+            return Some(project.join(format!("{}.yaml", DEFAULT_LIST)));
+        }
+    }
+
+    None
+}
+
+/// Works out which task file to use from the `--file`, `--list` and
+/// `--global` options.
+pub fn resolve_path(
+    file: Option<PathBuf>,
+    list: Option<&str>,
+    global: bool,
+) -> Result<PathBuf> {
+    if let Some(file) = file {
+        return Ok(file);
+    }
+    if let Some(name) = list {
+        return list_path(name);
+    }
+
+    match env::var_os("TORU_FILE") {
+        Some(file) if !file.is_empty() => return Ok(PathBuf::from(file)),
+        _ => {}
+    }
+
+    if !global {
+        if let Some(project) =
+            env::current_dir().ok().and_then(|d| discover(&d))
+        {
+            return Ok(project);
+        }
+    }
+
+    if let Some(legacy) = home_dir().map(|home| home.join(".toru.yaml")) {
+        if legacy.exists() {
+            return Ok(legacy);
+        }
+    }
+
+    // Without a home directory the working directory has to do
+    list_path(DEFAULT_LIST).or_else(|_| Ok(PathBuf::from(".toru.yaml")))
+}
+
+/// Returns the path of the `n`th backup of a task file, e.g. `.toru.yaml.1`.
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    with_suffix(path, &format!(".{}", n))
+}
+
+/// Returns the latest backup of the task file at `path`. The backups of a
+/// symlinked task file are kept next to the file the link points to.
+pub fn latest_backup(path: &Path) -> PathBuf {
+    let path = real_path(path).unwrap_or_else(|_| path.to_path_buf());
+    backup_path(&path, 1)
+}
+
+/// Returns the path of the file locked while a task file is open, e.g.
+/// `.toru.yaml.lock`. The task file itself is replaced on every save, so a
+/// lock on it would not outlive the first save.
+pub fn lock_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".lock")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// An exclusive lock on a task file, released when dropped.
+pub struct Lock {
+    _file: File,
+}
+
+/// Locks the task file at `path`, returning `None` if another instance already
+/// holds the lock.
+pub fn try_lock(path: &Path) -> Result<Option<Lock>> {
+    let file = open_lock_file(path)?;
+
+    match file.try_lock() {
+        Ok(()) => Ok(Some(Lock { _file: file })),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Locks the task file at `path`, waiting for any other instance to release
+/// it first.
+pub fn lock(path: &Path) -> Result<Lock> {
+    if let Some(lock) = try_lock(path)? {
+        return Ok(lock);
+    }
+
+    eprintln!(
+        "Waiting for another instance of toru to close {}...",
+        path.display()
+    );
+    let file = open_lock_file(path)?;
+    file.lock()?;
+
+    Ok(Lock { _file: file })
+}
+
+fn open_lock_file(path: &Path) -> Result<File> {
+    // This is the first time a new list is touched, and the data directory
+    // may not exist yet
+    if let Some(directory) = path.parent() {
+        if !directory.as_os_str().is_empty() {
+            fs::create_dir_all(directory)?;
+        }
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path(path))?;
+
+    Ok(file)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Every task in a list, referring to its parent and children by index.
+    Flat,
+    /// Every task written under its parent.
+    Nested,
+}
+
+impl FromStr for Format {
+    type Err = ToruError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(Self::Flat),
+            "nested" => Ok(Self::Nested),
+            _ => Err(Self::Err::InvalidArgument(format!(
+                "Unknown format '{}', expected flat or nested",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flat => write!(f, "flat"),
+            Self::Nested => write!(f, "nested"),
+        }
+    }
+}
+
+/// Returns the format a parsed file is written in. Files from before there
+/// was a choice of formats do not name one and are flat.
+fn detect_format(value: &Value) -> std::result::Result<Format, String> {
+    match value.get("format") {
+        None => Ok(Format::Flat),
+        Some(format) => serde_yaml::from_value(format.clone()).map_err(|_| {
+            String::from("unknown format, expected flat or nested")
+        }),
+    }
+}
+
+/// A task file along with the format it is written in.
+pub struct TaskFile {
+    path: PathBuf,
+    format: Format,
+    /// Set once the file has been backed up. Only the first save backs the
+    /// file up, so that autosaving after every change does not push the
+    /// versions from before the session out of the backups.
+    backed_up: bool,
+}
+
+/// A place a tree is kept.
+pub trait Storage {
+    /// The file the tree is kept in.
+    fn path(&self) -> &Path;
+
+    /// Loads the tree without checking that it is sound. Nothing stored yet is
+    /// an empty tree.
+    fn load_unchecked(&mut self) -> Result<Tree>;
+
+    /// Replaces the tree which is stored with another.
+    fn save(&mut self, tree: &Tree) -> Result<()>;
+
+    /// Returns the file the previous tree is kept in when a new one is saved,
+    /// if there is one.
+    fn backup(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Loads the tree. A tree which is damaged is an error, see the [`fsck`]
+    /// module. Tasks from a file written before tasks had uids are given
+    /// them, and keep them once the tree is saved.
+    ///
+    /// [`fsck`]: ../fsck/index.html
+    fn load(&mut self) -> Result<Tree> {
+        let tree = self.load_unchecked()?;
+        let problems = fsck::check(&tree);
+
+        if problems.is_empty() {
+            Ok(tree::assign_uids(tree))
+        } else {
+            Err(ToruError::DamagedTree(self.path().to_path_buf(), problems)
+                .into())
+        }
+    }
+}
+
+/// Opens the storage for a task file, which is an SQLite database if the file
+/// name ends in `.db`, `.sqlite` or `.sqlite3`, an event log if it ends in
+/// `.jsonl` and a YAML file otherwise.
+pub fn open(path: PathBuf) -> Result<Box<dyn Storage>> {
+    if is_log(&path) {
+        return Ok(Box::new(log::EventLog::new(path)));
+    }
+    if !is_database(&path) {
+        return Ok(Box::new(TaskFile::new(path)));
+    }
+
+    #[cfg(feature = "sqlite")]
+    return Ok(Box::new(sqlite::Database::new(path)));
+
+    #[cfg(not(feature = "sqlite"))]
+    return Err(format!(
+        "{} is an SQLite database, which this build of toru does not support",
+        path.display()
+    )
+    .into());
+}
+
+/// Checks whether a task file is an SQLite database by its name.
+pub fn is_database(path: &Path) -> bool {
+    let extension = path.extension().and_then(|e| e.to_str());
+    matches!(extension, Some("db") | Some("sqlite") | Some("sqlite3"))
+}
+
+/// Checks whether a task file is an event log by its name.
+pub fn is_log(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()) == Some("jsonl")
+}
+
+impl TaskFile {
+    /// Creates a handle to the task file at `path`. Until it is loaded the file
+    /// is taken to be flat.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            format: Format::Flat,
+            backed_up: false,
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+}
+
+impl Storage for TaskFile {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn backup(&self) -> Option<PathBuf> {
+        Some(latest_backup(&self.path))
+    }
+
+    /// Loads the tree stored in the file without checking that it is sound,
+    /// and notes the format the file is in. A file written in an older version
+    /// of the format is upgraded to the current one.
+    fn load_unchecked(&mut self) -> Result<Tree> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Tree::new())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // An empty file, e.g. one made with touch to mark a project, has no
+        // tasks yet
+        if contents.trim().is_empty() {
+            return Ok(Tree::new());
+        }
+
+        let value = serde_yaml::from_str(&contents)
+            .map_err(|e| load_error(&self.path, e))?;
+        let invalid = |message| ToruError::LoadError {
+            path: self.path.clone(),
+            location: None,
+            message,
+        };
+        let version = schema::version(&value).map_err(invalid)?;
+        let format = detect_format(&value).map_err(invalid)?;
+
+        let upgraded = if version == schema::VERSION {
+            None
+        } else {
+            Some(schema::migrate(value, version).map_err(invalid)?)
+        };
+        let tree = match format {
+            Format::Flat => deserialize(&contents, upgraded),
+            Format::Nested => deserialize::<Document>(&contents, upgraded)
+                .map(nested::into_tree),
+        };
+        let tree = tree.map_err(|e| load_error(&self.path, e))?;
+
+        self.format = format;
+        Ok(tree)
+    }
+
+    /// Atomically replaces the file with the tree, keeping the file it replaces
+    /// as the most recent backup the first time it is saved.
+    fn save(&mut self, tree: &Tree) -> Result<()> {
+        let contents = match self.format {
+            Format::Flat => serde_yaml::to_string(&schema::versioned(tree))?,
+            Format::Nested => {
+                let document = nested::from_tree(tree);
+                serde_yaml::to_string(&schema::versioned(&document))?
+            }
+        };
+
+        replace(&self.path, contents.as_bytes(), !self.backed_up)?;
+        self.backed_up = true;
+        Ok(())
+    }
+}
+
+/// Deserializes a file from its text, or from the value it was upgraded to if
+/// it was written in an older version of the format. Errors in the text point
+/// at a line.
+fn deserialize<T: DeserializeOwned>(
+    contents: &str,
+    upgraded: Option<Value>,
+) -> serde_yaml::Result<T> {
+    match upgraded {
+        Some(value) => serde_yaml::from_value(value),
+        None => serde_yaml::from_str(contents),
+    }
+}
+
+fn load_error(path: &Path, e: serde_yaml::Error) -> ToruError {
+    let location = e.location().map(|l| (l.line(), l.column()));
+
+    // The location is reported on its own
+    let mut message = e.to_string();
+    if let Some(end) = message.find(" at line ") {
+        message.truncate(end);
+    }
+
+    ToruError::LoadError {
+        path: path.to_path_buf(),
+        location,
+        message,
+    }
+}
+
+/// Atomically replaces the file at `path` with new contents, keeping the file
+/// it replaces as the most recent backup if `backup` is set. A symlink is
+/// followed so that the file it points to is replaced rather than the link,
+/// and the new file keeps the permissions of the old one.
+fn replace(path: &Path, contents: &[u8], backup: bool) -> Result<()> {
+    let path = real_path(path)?;
+    let path = path.as_path();
+    let directory = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return Err(format!("{} is not a file", path.display()).into()),
+    };
+    let temp_path =
+        directory.join(format!(".{}.{}.tmp", file_name, process::id()));
+
+    let permissions = match fs::metadata(path) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    if let Err(e) = write_synced(&temp_path, contents, permissions) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    if backup {
+        rotate_backups(path)?;
+    }
+    fs::rename(&temp_path, path)?;
+    sync_directory(&directory);
+
+    Ok(())
+}
+
+/// Returns the file a task file path refers to, following symlinks. A file
+/// which does not exist yet is its own path.
+fn real_path(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Ok(path) => Ok(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(path.to_path_buf()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_synced(
+    path: &Path,
+    contents: &[u8],
+    permissions: Option<fs::Permissions>,
+) -> Result<()> {
+    let mut file = File::create(path)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Shifts every backup up by one, dropping the oldest, and makes the current
+/// file the first backup. The current file is hard linked rather than moved so
+/// that it stays in place until the new version is renamed over it.
+fn rotate_backups(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    for n in (1..BACKUP_COUNT).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, n + 1))?;
+        }
+    }
+
+    let first = backup_path(path, 1);
+    let _ = fs::remove_file(&first);
+    if fs::hard_link(path, &first).is_err() {
+        fs::copy(path, &first)?;
+    }
+
+    Ok(())
+}
+
+/// Makes the rename durable. Not every platform can open a directory, so this
+/// is best effort.
+fn sync_directory(directory: &Path) {
+    if let Ok(dir) = File::open(directory) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{named, scratch_dir};
+    use crate::tree;
+
+    fn named_tree(name: &str) -> Tree {
+        tree::add(Tree::new(), named(name))
+    }
+
+    fn load(path: &Path) -> Result<Tree> {
+        TaskFile::new(path.to_path_buf()).load()
+    }
+
+    fn save(path: &Path, tree: &Tree) -> Result<()> {
+        TaskFile::new(path.to_path_buf()).save(tree)
+    }
+
+    #[test]
+    fn save_keeps_rotating_backups() {
+        let dir = scratch_dir("backups");
+        let path = dir.join(".toru.yaml");
+
+        for n in 0..BACKUP_COUNT + 2 {
+            save(&path, &named_tree(&format!("Save {}", n))).unwrap();
+        }
+
+        let latest = load(&path).unwrap();
+        assert_eq!(latest.children().next().unwrap().name(), "Save 6");
+
+        let previous = load(&backup_path(&path, 1)).unwrap();
+        assert_eq!(previous.children().next().unwrap().name(), "Save 5");
+
+        let oldest = load(&backup_path(&path, BACKUP_COUNT)).unwrap();
+        assert_eq!(oldest.children().next().unwrap().name(), "Save 1");
+        assert!(!backup_path(&path, BACKUP_COUNT + 1).exists());
+
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), BACKUP_COUNT + 1);
+
+        // Saving again through the same file only backs it up once
+        let mut file = TaskFile::new(path.clone());
+        file.save(&named_tree("Session 1")).unwrap();
+        file.save(&named_tree("Session 2")).unwrap();
+        let previous = load(&backup_path(&path, 1)).unwrap();
+        assert_eq!(previous.children().next().unwrap().name(), "Save 6");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_follows_symlinks_and_keeps_permissions() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = scratch_dir("symlink");
+        let target = dir.join("dotfiles.yaml");
+        let link = dir.join(".toru.yaml");
+        save(&target, &named_tree("a")).unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600))
+            .unwrap();
+        symlink(&target, &link).unwrap();
+
+        save(&link, &named_tree("b")).unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        let saved = load(&target).unwrap();
+        assert_eq!(saved.children().next().unwrap().name(), "b");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // The backup is kept next to the file the link points to
+        assert_eq!(latest_backup(&link), backup_path(&target, 1));
+        let previous = load(&latest_backup(&link)).unwrap();
+        assert_eq!(previous.children().next().unwrap().name(), "a");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_one_lock_at_a_time() {
+        let dir = scratch_dir("lock");
+        let path = dir.join(".toru.yaml");
+
+        let lock = try_lock(&path).unwrap();
+        assert!(lock.is_some());
+        assert!(try_lock(&path).unwrap().is_none());
+
+        drop(lock);
+        assert!(try_lock(&path).unwrap().is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn load_error(path: &Path) -> ToruError {
+        match load(path) {
+            Ok(_) => panic!("{} loaded", path.display()),
+            Err(e) => *e.downcast::<ToruError>().unwrap(),
+        }
+    }
+
+    #[test]
+    fn damaged_files_are_errors() {
+        let dir = scratch_dir("damaged");
+        let path = dir.join(".toru.yaml");
+
+        fs::write(&path, "ptr: 0\ntasks: [\n  - name: a\n").unwrap();
+        match load_error(&path) {
+            ToruError::LoadError { location, .. } => {
+                assert_eq!(location.map(|(line, _)| line), Some(3));
+            }
+            e => panic!("Unexpected error: {}", e),
+        }
+
+        let mut tree = named_tree("a");
+        tree.set_ptr(5);
+        save(&path, &tree).unwrap();
+        match load_error(&path) {
+            ToruError::DamagedTree(_, problems) => {
+                assert_eq!(problems.len(), 1)
+            }
+            e => panic!("Unexpected error: {}", e),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn format_is_kept() {
+        let dir = scratch_dir("format");
+        let path = dir.join(".toru.yaml");
+
+        let mut file = TaskFile::new(path.clone());
+        file.set_format(Format::Nested);
+        file.save(&named_tree("a")).unwrap();
+
+        let mut file = TaskFile::new(path.clone());
+        let tree = file.load().unwrap();
+        assert_eq!(file.format(), Format::Nested);
+        assert_eq!(tree.children().next().unwrap().name(), "a");
+
+        file.save(&named_tree("b")).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("format: nested"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn discover_project_files() {
+        let dir = scratch_dir("discover");
+        let nested = dir.join("a").join("b").join("c");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir(dir.join("a").join(".toru")).unwrap();
+
+        let tasks = dir.join("a").join(".toru").join("tasks.yaml");
+        assert_eq!(discover(&nested), Some(tasks));
+
+        let closer = dir.join("a").join("b").join(".toru.yaml");
+        fs::write(&closer, "").unwrap();
+        assert_eq!(discover(&nested), Some(closer.clone()));
+        assert_eq!(load(&closer).unwrap().tasks().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
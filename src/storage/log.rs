@@ -0,0 +1,441 @@
+//! This module keeps a tree as a log of the operations made on it, one JSON
+//! object per line. Saving appends the operations since the last save rather
+//! than rewriting the file, and loading replays the log from its latest
+//! snapshot.
+//!
+//! ```text
+//! {"time":"2021-03-01T17:00:00Z","event":"snapshot","version":1,"tree":{..}}
+//! {"time":"2021-03-01T17:05:40Z","event":"op","op":"complete","uid":2973105}
+//! {"time":"2021-03-01T17:06:02Z","event":"op","op":"trash","uids":[2973105]}
+//! {"time":"2021-03-01T17:06:02Z","event":"cursor","ptr":0}
+//! ```
+//!
+//! The operations are those recorded in the tree's journal, see the [`tree`]
+//! module, so a deletion or a move is logged as one event naming the task
+//! rather than as the tasks whose indices it shifted. Tasks are named by
+//! their uids, which unlike their indices stay the same as the tree changes.
+//! A tree which was changed other than through its journal, e.g. repaired by
+//! `toru fsck`, cannot be logged as operations and is saved as a snapshot.
+//!
+//! Once [`COMPACT_AFTER`] events follow the latest snapshot, the log is
+//! compacted: it is replaced by a single snapshot of the tree, the way a YAML
+//! task file is replaced, keeping the uncompacted log as a backup.
+//!
+//! [`COMPACT_AFTER`]: ./constant.COMPACT_AFTER.html
+//! [`tree`]: ../../tree/index.html
+
+use super::{replace, Storage};
+use crate::fsck;
+use crate::schema;
+use crate::tree::{self, Op, Tree};
+use crate::{Result, ToruError};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use time::OffsetDateTime;
+
+/// The number of events after the latest snapshot which are replayed before
+/// the log is compacted.
+pub const COMPACT_AFTER: usize = 1000;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Event {
+    /// The whole tree, which replaces everything logged before it.
+    Snapshot {
+        version: u64,
+        tree: Tree,
+    },
+    Op(Op),
+    /// The current task moved, to the task with the uid `ptr`.
+    Cursor {
+        ptr: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    time: String,
+    #[serde(flatten)]
+    event: Event,
+}
+
+/// A snapshot as it is written, which borrows the tree rather than owning it
+/// like an [`Event`].
+///
+/// [`Event`]: ./enum.Event.html
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    time: String,
+    event: &'static str,
+    version: u64,
+    tree: &'a Tree,
+}
+
+fn now() -> String {
+    OffsetDateTime::now_utc().format("%FT%TZ")
+}
+
+/// The tree as it was last loaded or saved.
+struct Saved {
+    tree: Tree,
+    /// The length of the tree's journal at the time.
+    journaled: usize,
+}
+
+impl Saved {
+    fn new(tree: &Tree) -> Self {
+        Self {
+            tree: tree.snapshot(),
+            journaled: tree.journal().len(),
+        }
+    }
+
+    /// Returns the events which turn the saved tree into `tree`, or `None` if
+    /// its journal does not account for how it changed.
+    fn changes(&self, tree: &Tree) -> Option<Vec<Event>> {
+        let ops = tree.journal().get(self.journaled..)?;
+        let mut replayed = self.tree.clone();
+        for op in ops {
+            replayed = tree::replay(replayed, op.clone()).ok()?;
+        }
+        if replayed.tasks() != tree.tasks() || replayed.trash() != tree.trash()
+        {
+            return None;
+        }
+
+        let mut events: Vec<Event> =
+            ops.iter().cloned().map(Event::Op).collect();
+        if replayed.ptr() != tree.ptr() {
+            events.push(Event::Cursor {
+                ptr: tree.uid(tree.ptr()),
+            });
+        }
+
+        Some(events)
+    }
+}
+
+pub struct EventLog {
+    path: PathBuf,
+    /// `None` until a log which exists is loaded, in which case the next save
+    /// writes a snapshot.
+    saved: Option<Saved>,
+    /// The number of events logged after the latest snapshot.
+    changes: usize,
+    /// Whether the last line was cut short, e.g. by a crash while appending.
+    torn: bool,
+}
+
+impl EventLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            saved: None,
+            changes: 0,
+            torn: false,
+        }
+    }
+
+    fn load_error(&self, line: usize, message: String) -> ToruError {
+        ToruError::LoadError {
+            path: self.path.clone(),
+            location: Some((line, 1)),
+            message,
+        }
+    }
+
+    /// Replaces the log with a snapshot of the tree.
+    fn compact(&mut self, tree: &Tree) -> Result<()> {
+        let snapshot = Snapshot {
+            time: now(),
+            event: "snapshot",
+            version: schema::VERSION,
+            tree,
+        };
+        let mut contents = serde_json::to_string(&snapshot)?;
+        contents.push('\n');
+        replace(&self.path, contents.as_bytes(), true)?;
+
+        self.saved = Some(Saved::new(tree));
+        self.changes = 0;
+        self.torn = false;
+        Ok(())
+    }
+}
+
+fn apply(tree: Tree, event: Event) -> std::result::Result<Tree, String> {
+    match event {
+        Event::Snapshot { version, tree } => {
+            if version > schema::VERSION {
+                return Err(format!(
+                    "written in format version {} by a newer toru, this one \
+                     reads up to version {}",
+                    version,
+                    schema::VERSION
+                ));
+            }
+            Ok(tree)
+        }
+        Event::Op(op) => tree::replay(tree, op),
+        Event::Cursor { ptr } => {
+            let mut tree = tree;
+            let idx = tree
+                .find_uid(ptr)
+                .ok_or_else(|| format!("no task has the uid {:016x}", ptr))?;
+            tree.set_ptr(idx);
+            Ok(tree)
+        }
+    }
+}
+
+impl Storage for EventLog {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load_unchecked(&mut self) -> Result<Tree> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.saved = None;
+                return Ok(Tree::new());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut tree = Tree::new();
+        let mut changes = 0;
+        let mut torn = false;
+        let last = contents.lines().count();
+
+        for (n, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let record: Record = match serde_json::from_str(line) {
+                Ok(record) => record,
+                // The change on a line cut short was never saved
+                Err(_) if n + 1 == last && !contents.ends_with('\n') => {
+                    torn = true;
+                    break;
+                }
+                Err(e) => {
+                    // The line is reported on its own
+                    let mut message = e.to_string();
+                    if let Some(end) = message.find(" at line ") {
+                        message.truncate(end);
+                    }
+                    return Err(self.load_error(n + 1, message).into());
+                }
+            };
+
+            changes = match record.event {
+                Event::Snapshot { .. } => 0,
+                _ => changes + 1,
+            };
+            tree = apply(tree, record.event)
+                .map_err(|message| self.load_error(n + 1, message))?;
+        }
+
+        // The tasks are given the uids they will be known by from here on, as
+        // they would be by load, which only a sound tree can be given
+        if fsck::check(&tree).is_empty() {
+            tree = tree::assign_uids(tree);
+        }
+        // The operations replayed are in the log already
+        let tree = tree.snapshot();
+        self.saved = Some(Saved::new(&tree));
+        self.changes = changes;
+        self.torn = torn;
+        Ok(tree)
+    }
+
+    /// Appends the operations made on the tree since the log was last loaded
+    /// or saved, compacting it instead if it has grown long or the tree
+    /// changed in a way its journal does not account for.
+    fn save(&mut self, tree: &Tree) -> Result<()> {
+        let events = match &self.saved {
+            // Appending after a line cut short would join the two
+            Some(saved) if !self.torn => saved.changes(tree),
+            _ => None,
+        };
+        let events = match events {
+            Some(events) => events,
+            None => return self.compact(tree),
+        };
+        if events.is_empty() {
+            return Ok(());
+        }
+        if self.changes + events.len() > COMPACT_AFTER {
+            return self.compact(tree);
+        }
+
+        let time = now();
+        let count = events.len();
+        let mut contents = String::new();
+        for event in events {
+            let record = Record {
+                time: time.clone(),
+                event,
+            };
+            contents.push_str(&serde_json::to_string(&record)?);
+            contents.push('\n');
+        }
+
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_data()?;
+
+        self.saved = Some(Saved::new(tree));
+        self.changes += count;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::backup_path;
+    use crate::test_util::{named, scratch_dir};
+
+    fn names(tree: &Tree) -> Vec<&str> {
+        tree.children().map(|task| task.name().as_str()).collect()
+    }
+
+    fn events(path: &Path) -> Vec<Event> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Record>(line).unwrap().event)
+            .collect()
+    }
+
+    #[test]
+    fn operations_are_appended_and_replayed() {
+        let path = scratch_dir("log-replay").join("tasks.jsonl");
+        let mut log = EventLog::new(path.clone());
+        let mut tree = log.load().unwrap();
+
+        tree = tree::add(tree, named("a"));
+        tree = tree::add(tree, named("b"));
+        log.save(&tree).unwrap();
+        tree = tree::add(tree, named("c"));
+        tree = tree::complete(tree, 3);
+        log.save(&tree).unwrap();
+        let (a, c) = (tree.uid(1), tree.uid(3));
+
+        // Deleting a task shifts the others down, but is one operation
+        let logged = events(&path).len();
+        tree = tree::delete(tree, 1);
+        log.save(&tree).unwrap();
+        let events = events(&path);
+        assert_eq!(events.len(), logged + 1);
+        assert!(matches!(events[0], Event::Snapshot { .. }));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            Event::Op(Op::Complete { uid }) if *uid == c
+        )));
+        assert!(matches!(
+            events.last(),
+            Some(Event::Op(Op::Delete { uids })) if uids == &vec![a]
+        ));
+
+        let loaded = EventLog::new(path.clone()).load().unwrap();
+        assert_eq!(names(&loaded), vec!["b", "c"]);
+        assert!(loaded.tasks()[2].is_complete());
+        assert_eq!(loaded.tasks(), tree.tasks());
+
+        // A change cut short is dropped, and the log is rewritten before
+        // anything else is appended
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, contents + "{\"time\":").unwrap();
+        let mut log = EventLog::new(path.clone());
+        let tree = log.load().unwrap();
+        assert_eq!(names(&tree), vec!["b", "c"]);
+        log.save(&tree).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn moves_are_replayed_after_reloading() {
+        let path = scratch_dir("log-move").join("tasks.jsonl");
+        let mut log = EventLog::new(path.clone());
+        let mut tree = tree::add(Tree::new(), named("a"));
+        tree = tree::add(tree, named("b"));
+        log.save(&tree).unwrap();
+
+        // Appended to a log which was loaded rather than saved
+        let mut log = EventLog::new(path.clone());
+        let mut tree = log.load().unwrap();
+        tree = tree::relocate(tree, 2, 1);
+        tree = tree::descend(tree, 1);
+        log.save(&tree).unwrap();
+        let (b, a) = (tree.uid(2), tree.uid(1));
+        let events = events(&path);
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            events[1],
+            Event::Op(Op::Move { uid, parent }) if uid == b && parent == a
+        ));
+        assert!(matches!(events[2], Event::Cursor { ptr } if ptr == a));
+
+        let loaded = EventLog::new(path.clone()).load().unwrap();
+        assert_eq!(loaded.ptr(), 1);
+        assert_eq!(loaded.ancestry(2), vec![0, 1, 2]);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn changes_outside_the_journal_are_compacted() {
+        let path = scratch_dir("log-raw").join("tasks.jsonl");
+        let mut log = EventLog::new(path.clone());
+        let mut tree = tree::add(Tree::new(), named("a"));
+        log.save(&tree).unwrap();
+        tree = tree::add(tree, named("b"));
+        log.save(&tree).unwrap();
+
+        let renamed = tree.tasks()[1].clone().set_name(String::from("c"));
+        tree = tree.replace_task(1, renamed);
+        log.save(&tree).unwrap();
+        assert_eq!(events(&path).len(), 1);
+
+        let loaded = EventLog::new(path.clone()).load().unwrap();
+        assert_eq!(names(&loaded), vec!["c", "b"]);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn long_logs_are_compacted() {
+        let path = scratch_dir("log-compact").join("tasks.jsonl");
+        let mut log = EventLog::new(path.clone());
+        let mut tree = Tree::new();
+        log.save(&tree).unwrap();
+
+        for n in 0..COMPACT_AFTER {
+            tree = tree::add(tree, named(&n.to_string()));
+            log.save(&tree).unwrap();
+        }
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines, 1 + COMPACT_AFTER);
+
+        tree = tree::add(tree, named("last"));
+        log.save(&tree).unwrap();
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines, 1);
+        assert!(backup_path(&path, 1).exists());
+
+        let loaded = EventLog::new(path.clone()).load().unwrap();
+        assert_eq!(loaded.tasks().len(), COMPACT_AFTER + 2);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,407 @@
+//! This module keeps a tree in an SQLite database. Every task is a row of the
+//! `tasks` table keyed by its index, and the current task and the trash are
+//! kept in the `state` table. Saving only writes the rows of the tasks which
+//! changed since the tree was last loaded or saved, all in one transaction, so
+//! a save is as cheap as the change and is never half applied.
+//!
+//! The layout of the database is versioned with `PRAGMA user_version` and
+//! upgraded by [`MIGRATIONS`] as it is opened.
+//!
+//! [`MIGRATIONS`]: ./constant.MIGRATIONS.html
+
+use super::Storage;
+use crate::task::Task;
+use crate::tree::Tree;
+use crate::{Result, ToruError};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::{Path, PathBuf};
+use time::PrimitiveDateTime;
+
+/// The statements upgrading the database by one version. The first creates
+/// the tables of version 1 in an empty database.
+const MIGRATIONS: [&str; 3] = [
+    "
+    CREATE TABLE tasks (
+        id INTEGER PRIMARY KEY,
+        parent INTEGER,
+        position INTEGER,
+        name TEXT NOT NULL,
+        due TEXT,
+        complete INTEGER NOT NULL,
+        tags TEXT NOT NULL,
+        trashed_from INTEGER
+    );
+    CREATE TABLE state (
+        key TEXT PRIMARY KEY,
+        value INTEGER
+    );
+",
+    // Version 2 adds uids
+    "ALTER TABLE tasks ADD COLUMN uid INTEGER;",
+    // Version 3 adds priorities
+    "ALTER TABLE tasks ADD COLUMN priority TEXT;",
+];
+
+/// Due dates are stored in a format which sorts as text.
+const DUE_FORMAT: &str = "%F %T";
+
+/// A task as it is stored in a row. A task which its parent does not list has
+/// no position.
+#[derive(Clone, PartialEq)]
+struct Row {
+    parent: Option<usize>,
+    position: Option<usize>,
+    name: String,
+    due: Option<String>,
+    complete: bool,
+    /// Tags separated by newlines.
+    tags: String,
+    priority: Option<String>,
+    trashed_from: Option<usize>,
+    /// SQLite integers are signed, so a uid is stored with the same bits.
+    uid: Option<i64>,
+}
+
+impl Row {
+    fn new(tree: &Tree, idx: usize) -> Self {
+        let task = &tree.tasks()[idx];
+        let position = task
+            .parent()
+            .and_then(|parent| tree.task(parent))
+            .and_then(|parent| {
+                parent.children().iter().position(|&child| child == idx)
+            });
+
+        Self {
+            parent: task.parent(),
+            position,
+            name: task.name().clone(),
+            due: task.due().map(|due| due.format(DUE_FORMAT)),
+            complete: task.is_complete(),
+            tags: task.tags().join("\n"),
+            priority: task.priority().map(String::from),
+            trashed_from: task.trashed_from(),
+            uid: task.uid().map(|uid| uid as i64),
+        }
+    }
+}
+
+pub struct Database {
+    path: PathBuf,
+    /// Opened the first time the database is used.
+    connection: Option<Connection>,
+    /// The rows as they were last loaded or saved.
+    rows: Vec<Row>,
+}
+
+impl Database {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            connection: None,
+            rows: Vec::new(),
+        }
+    }
+
+    fn load_error(&self, message: String) -> ToruError {
+        ToruError::LoadError {
+            path: self.path.clone(),
+            location: None,
+            message,
+        }
+    }
+}
+
+/// Opens the database if it is not open yet, upgrading it to the current
+/// version.
+fn connect<'a>(
+    connection: &'a mut Option<Connection>,
+    path: &Path,
+) -> Result<&'a mut Connection> {
+    if let Some(connection) = connection {
+        return Ok(connection);
+    }
+
+    let mut opened = Connection::open(path)?;
+    migrate(&mut opened, path)?;
+    Ok(connection.insert(opened))
+}
+
+fn migrate(connection: &mut Connection, path: &Path) -> Result<()> {
+    let version: usize =
+        connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(ToruError::LoadError {
+            path: path.to_path_buf(),
+            location: None,
+            message: format!(
+                "written in database version {} by a newer toru, this one \
+                 reads up to version {}",
+                version,
+                MIGRATIONS.len()
+            ),
+        }
+        .into());
+    }
+
+    for (n, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", n + 1)?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+fn task(row: &Row) -> std::result::Result<Task, time::ParseError> {
+    let mut task = Task::new()
+        .set_name(row.name.clone())
+        .set_priority(row.priority.as_ref().and_then(|p| p.chars().next()))
+        .set_trashed_from(row.trashed_from)
+        .set_uid(row.uid.map(|uid| uid as u64));
+    if let Some(parent) = row.parent {
+        task = task.set_parent(parent);
+    }
+    if let Some(due) = &row.due {
+        task = task.set_due(PrimitiveDateTime::parse(due, DUE_FORMAT)?);
+    }
+    if row.complete {
+        task = task.complete();
+    }
+    for tag in row.tags.lines() {
+        task = task.add_tag(tag.to_string());
+    }
+
+    Ok(task)
+}
+
+impl Storage for Database {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load_unchecked(&mut self) -> Result<Tree> {
+        // Opening a database which does not exist would create it
+        if !self.path.exists() {
+            self.rows.clear();
+            return Ok(Tree::new());
+        }
+
+        let connection = connect(&mut self.connection, &self.path)?;
+        let mut statement = connection.prepare(
+            "SELECT id, parent, position, name, due, complete, tags, \
+             trashed_from, uid, priority FROM tasks ORDER BY id",
+        )?;
+        let rows = statement
+            .query_map([], |row| {
+                let id: usize = row.get(0)?;
+                Ok((
+                    id,
+                    Row {
+                        parent: row.get(1)?,
+                        position: row.get(2)?,
+                        name: row.get(3)?,
+                        due: row.get(4)?,
+                        complete: row.get(5)?,
+                        tags: row.get(6)?,
+                        trashed_from: row.get(7)?,
+                        uid: row.get(8)?,
+                        priority: row.get(9)?,
+                    },
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        drop(statement);
+
+        let state = |key: &str| -> rusqlite::Result<Option<usize>> {
+            connection
+                .query_row(
+                    "SELECT value FROM state WHERE key = ?1",
+                    [key],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map(Option::flatten)
+        };
+        let ptr = state("ptr")?.unwrap_or(0);
+        let trash = state("trash")?;
+
+        if rows.is_empty() {
+            self.rows.clear();
+            return Ok(Tree::new());
+        }
+        if let Some((_, (id, _))) =
+            rows.iter().enumerate().find(|(n, (id, _))| n != id)
+        {
+            return Err(self
+                .load_error(format!("task {} is out of sequence", id))
+                .into());
+        }
+
+        let rows: Vec<Row> = rows.into_iter().map(|(_, row)| row).collect();
+        let mut tasks = Vec::with_capacity(rows.len());
+        for (id, row) in rows.iter().enumerate() {
+            let task = task(row).map_err(|e| {
+                self.load_error(format!(
+                    "task {} has an invalid due date: {}",
+                    id, e
+                ))
+            })?;
+            tasks.push(task);
+        }
+
+        // Children are listed in the order of their positions
+        let mut listed: Vec<(usize, usize, usize)> = rows
+            .iter()
+            .enumerate()
+            .filter_map(|(id, row)| Some((row.parent?, row.position?, id)))
+            .filter(|&(parent, _, _)| parent < tasks.len())
+            .collect();
+        listed.sort_unstable();
+        let mut children = vec![Vec::new(); tasks.len()];
+        for (parent, _, id) in listed {
+            children[parent].push(id);
+        }
+
+        let mut tree = Tree::new();
+        *tree.tasks_mut() = tasks
+            .into_iter()
+            .zip(children)
+            .map(|(task, children)| task.set_children(children))
+            .collect();
+        tree.set_ptr(ptr);
+        tree.set_trash(trash);
+
+        self.rows = rows;
+        Ok(tree)
+    }
+
+    /// Writes the tasks which changed since the database was last loaded or
+    /// saved in a single transaction.
+    fn save(&mut self, tree: &Tree) -> Result<()> {
+        let rows: Vec<Row> = (0..tree.tasks().len())
+            .map(|idx| Row::new(tree, idx))
+            .collect();
+
+        let connection = connect(&mut self.connection, &self.path)?;
+        let transaction = connection.transaction()?;
+        {
+            let mut replace = transaction.prepare(
+                "INSERT OR REPLACE INTO tasks (id, parent, position, name, \
+                 due, complete, tags, trashed_from, uid, priority) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            )?;
+            for (id, row) in rows.iter().enumerate() {
+                if self.rows.get(id) == Some(row) {
+                    continue;
+                }
+
+                replace.execute(params![
+                    id,
+                    row.parent,
+                    row.position,
+                    row.name,
+                    row.due,
+                    row.complete,
+                    row.tags,
+                    row.trashed_from,
+                    row.uid,
+                    row.priority,
+                ])?;
+            }
+        }
+
+        transaction
+            .execute("DELETE FROM tasks WHERE id >= ?1", [rows.len()])?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO state (key, value) VALUES ('ptr', ?1), \
+             ('trash', ?2)",
+            params![tree.ptr(), tree.trash()],
+        )?;
+        transaction.commit()?;
+
+        self.rows = rows;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fsck;
+    use crate::test_util::{named, scratch_dir};
+    use crate::tree;
+    use std::fs;
+
+    #[test]
+    fn round_trip() {
+        let path = scratch_dir("sqlite-round-trip").join("tasks.db");
+        let due = crate::task::parse_due("2021-03-01 05:00 pm").unwrap();
+
+        let mut tree = Tree::new();
+        tree = tree::add(tree, named("report").set_due(due));
+        tree = tree::add(
+            tree,
+            named("idea")
+                .add_tag(String::from("work"))
+                .set_priority(Some('A')),
+        );
+        tree = tree::descend(tree, 1);
+        tree = tree::add(tree, named("figures"));
+        tree = tree::add(tree, named("draft"));
+        tree = tree::complete(tree, 3);
+        tree = tree::trash(tree, 2);
+        let tree = tree::assign_uids(tree);
+
+        Database::new(path.clone()).save(&tree).unwrap();
+        let loaded = Database::new(path.clone()).load().unwrap();
+        assert!(fsck::check(&loaded).is_empty());
+
+        assert_eq!(loaded.ptr(), tree.ptr());
+        assert_eq!(loaded.trash(), tree.trash());
+        for (loaded, task) in loaded.tasks().iter().zip(tree.tasks()) {
+            assert_eq!(loaded.name(), task.name());
+            assert_eq!(loaded.due(), task.due());
+            assert_eq!(loaded.tags(), task.tags());
+            assert_eq!(loaded.uid(), task.uid());
+            assert_eq!(loaded.priority(), task.priority());
+            assert_eq!(loaded.children(), task.children());
+            assert_eq!(loaded.parent(), task.parent());
+            assert_eq!(loaded.trashed_from(), task.trashed_from());
+            assert_eq!(loaded.is_complete(), task.is_complete());
+        }
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn only_changes_are_written() {
+        let path = scratch_dir("sqlite-changes").join("tasks.db");
+        let mut tree = Tree::new();
+        tree = tree::add(tree, named("a"));
+        tree = tree::add(tree, named("b"));
+        tree = tree::add(tree, named("c"));
+
+        let mut database = Database::new(path.clone());
+        database.save(&tree).unwrap();
+
+        // Changed behind toru's back, so rewriting it would undo this
+        Connection::open(&path)
+            .unwrap()
+            .execute("UPDATE tasks SET name = 'untouched' WHERE id = 1", [])
+            .unwrap();
+
+        let b = tree.tasks()[2].clone().set_name(String::from("bee"));
+        tree = tree.replace_task(2, b);
+        tree = tree::delete(tree, 3);
+        database.save(&tree).unwrap();
+
+        let loaded = Database::new(path.clone()).load().unwrap();
+        let names: Vec<&str> =
+            loaded.children().map(|task| task.name().as_str()).collect();
+        assert_eq!(names, vec!["untouched", "bee"]);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
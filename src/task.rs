@@ -4,14 +4,55 @@
 //! [`Task`]: ./struct.Task.html
 
 use serde::{Deserialize, Serialize};
-use std::fmt;
-use time::PrimitiveDateTime;
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use time::{Date, PrimitiveDateTime};
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+/// The format due dates are entered in, e.g. "2020-04-01 05:30 pm".
+pub const DATE_FORMAT: &str = "%F %I:%M %p";
+
+/// Parses a due date written in [`DATE_FORMAT`], or just its date in which
+/// case the task is due at midnight. Either "pm" or "PM" may be used.
+///
+/// [`DATE_FORMAT`]: ./constant.DATE_FORMAT.html
+pub fn parse_due(s: &str) -> Result<PrimitiveDateTime, time::ParseError> {
+    // The meridiem is only recognised in lower case
+    let s = s.trim().to_lowercase();
+
+    time::parse(&s, DATE_FORMAT).or_else(|e| {
+        // A date followed by a time which failed to parse is not a date on
+        // its own
+        if s.contains(char::is_whitespace) {
+            return Err(e);
+        }
+
+        time::parse::<Date>(&s, "%F")
+            .map(|date| date.midnight())
+            .map_err(|_| e)
+    })
+}
+
+/// Returns a new uid for a task. It is random, so that tasks added by
+/// different instances of toru or to different files do not share one.
+pub fn new_uid() -> u64 {
+    static COUNT: AtomicU64 = AtomicU64::new(0);
+
+    // Every RandomState is seeded differently
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNT.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 /// Enum that represents the current state of a task. Currently this is
 /// primarily used to differentiate between pending and completed tasks,
 /// however, it may see some other uses in the future.
 enum Status {
+    #[default]
     Pending,
     Complete,
 }
@@ -24,7 +65,7 @@ enum Status {
 /// with [`Tree`] in order to be useful.
 ///
 /// [`Tree`]: ../tree/struct.Tree.html
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Task {
     /// The parent (if any) of the task. The only task that should not have a
     /// parent is the root task.
@@ -35,15 +76,36 @@ pub struct Task {
     name: String,
     /// The due date (if any) of the task.
     #[doc(hidden)]
+    #[serde(default)]
     due: Option<PrimitiveDateTime>,
     /// The status of the task. See the [`Status`] enum.
     /// [`Status`]: ./enum.Status.html
     #[doc(hidden)]
+    #[serde(default)]
     status: Status,
     /// A vector of unsigned integers that holds the indices to the task's
     /// children.
     #[doc(hidden)]
+    #[serde(default)]
     children: Vec<usize>,
+    /// Free form labels attached to the task.
+    #[doc(hidden)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// The priority of the task from 'A', the highest, to 'Z'.
+    #[doc(hidden)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<char>,
+    /// The parent the task had before it was moved to the trash. It is used
+    /// to put the task back where it came from when it is restored.
+    #[doc(hidden)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trashed_from: Option<usize>,
+    /// An id which, unlike the task's index, stays the same for as long as
+    /// the task exists. It is given to the task when it is added to a tree.
+    #[doc(hidden)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uid: Option<u64>,
 }
 
 impl Task {
@@ -67,6 +129,10 @@ impl Task {
             due: None,
             status: Status::Pending,
             children: Vec::new(),
+            tags: Vec::new(),
+            priority: None,
+            trashed_from: None,
+            uid: None,
         }
     }
 
@@ -152,6 +218,122 @@ impl Task {
         self
     }
 
+    /// Removes the parent of the task, making it a top level task like the
+    /// root or the trash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut task = Task::new().set_parent(3);
+    /// assert_eq!(task.parent(), Some(3));
+    ///
+    /// task = task.remove_parent();
+    /// assert_eq!(task.parent(), None);
+    /// ```
+    ///
+    pub fn remove_parent(mut self) -> Self {
+        self.parent = None;
+        self
+    }
+
+    /// Returns the tags of a task.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let task = Task::new().add_tag(String::from("work"));
+    /// assert_eq!(task.tags(), vec![String::from("work")]);
+    /// ```
+    ///
+    pub fn tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
+    /// Returns the priority of the task, if it has one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let task = Task::new().set_priority(Some('A'));
+    /// assert_eq!(task.priority(), Some('A'));
+    /// ```
+    ///
+    pub fn priority(&self) -> Option<char> {
+        self.priority
+    }
+
+    /// Sets the priority of the task, from 'A', the highest, to 'Z'.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut task = Task::new().set_priority(Some('B'));
+    /// task = task.set_priority(None);
+    /// assert_eq!(task.priority(), None);
+    /// ```
+    ///
+    pub fn set_priority(mut self, priority: Option<char>) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns the uid of the task, unless it has not been added to a tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let task = Task::new().set_uid(Some(7));
+    /// assert_eq!(task.uid(), Some(7));
+    /// ```
+    ///
+    pub fn uid(&self) -> Option<u64> {
+        self.uid
+    }
+
+    /// Sets the uid of the task.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut task = Task::new().set_uid(Some(7));
+    /// task = task.set_uid(None);
+    /// assert_eq!(task.uid(), None);
+    /// ```
+    ///
+    pub fn set_uid(mut self, uid: Option<u64>) -> Self {
+        self.uid = uid;
+        self
+    }
+
+    /// Returns the parent the task was moved to the trash from, if it is in
+    /// the trash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let task = Task::new().set_trashed_from(Some(2));
+    /// assert_eq!(task.trashed_from(), Some(2));
+    /// ```
+    ///
+    pub fn trashed_from(&self) -> Option<usize> {
+        self.trashed_from
+    }
+
+    /// Sets the parent the task was moved to the trash from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut task = Task::new().set_trashed_from(Some(2));
+    /// task = task.set_trashed_from(None);
+    /// assert_eq!(task.trashed_from(), None);
+    /// ```
+    ///
+    pub fn set_trashed_from(mut self, parent: Option<usize>) -> Self {
+        self.trashed_from = parent;
+        self
+    }
+
     /// Sets the name of the task.
     ///
     /// # Examples
@@ -170,6 +352,24 @@ impl Task {
         self
     }
 
+    /// Adds a tag to the task, unless it already has it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let task = Task::new()
+    ///     .add_tag(String::from("work"))
+    ///     .add_tag(String::from("work"));
+    /// assert_eq!(task.tags().len(), 1);
+    /// ```
+    ///
+    pub fn add_tag(mut self, tag: String) -> Self {
+        if !self.tags.contains(&tag) {
+            self.tags.push(tag);
+        }
+        self
+    }
+
     /// Sets the due date of the task.
     ///
     /// # Examples
@@ -242,6 +442,23 @@ impl Task {
         self
     }
 
+    /// Replaces all of a task's children.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut task = Task::new().add_child(1).add_child(2);
+    /// assert_eq!(task.children(), vec![1, 2]);
+    ///
+    /// task = task.set_children(vec![3]);
+    /// assert_eq!(task.children(), vec![3]);
+    /// ```
+    ///
+    pub fn set_children(mut self, children: Vec<usize>) -> Self {
+        self.children = children;
+        self
+    }
+
     /// Removes a child index from a task's children.
     ///
     /// # Examples
@@ -262,25 +479,28 @@ impl Task {
         self
     }
 
-    /// Replace a child index with another index.
+    /// Rewrites every index the task holds through `f`. Indices for which `f`
+    /// returns `None` are dropped, which is how references to deleted tasks
+    /// are removed.
     ///
     /// # Examples
     ///
     /// ```
-    /// let mut task = Task::new().add_child(1).add_child(2);
-    /// task = task.replace_child(1, 3);
+    /// let task = Task::new().set_parent(4).add_child(5).add_child(6);
+    /// let task =
+    ///     task.reindex(|idx| if idx == 5 { None } else { Some(idx - 1) });
     ///
-    /// assert_eq!(task.children(), vec![3, 2]);
+    /// assert_eq!(task.parent(), Some(3));
+    /// assert_eq!(task.children(), vec![5]);
     /// ```
     ///
-    pub fn replace_child(mut self, old_child: usize, new_child: usize) -> Self {
-        let new_children: Vec<usize> = self
-            .children
-            .iter()
-            .map(|&index| if index == old_child { new_child } else { index })
-            .collect();
-
-        self.children = new_children;
+    pub fn reindex<F>(mut self, f: F) -> Self
+    where
+        F: Fn(usize) -> Option<usize>,
+    {
+        self.parent = self.parent.and_then(&f);
+        self.children = self.children.iter().filter_map(|&i| f(i)).collect();
+        self.trashed_from = self.trashed_from.and_then(&f);
         self
     }
 
@@ -0,0 +1,17 @@
+//! This module holds the fixtures shared by the tests of other modules.
+
+use crate::task::Task;
+use std::{env, fs, path::PathBuf, process};
+
+/// Returns a new task with a name.
+pub fn named(name: &str) -> Task {
+    Task::new().set_name(name.to_string())
+}
+
+/// Returns an empty directory for a test to write files in.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("toru-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
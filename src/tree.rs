@@ -1,11 +1,20 @@
 //! This module defines the [`Tree`] structure which forms the basis of all
 //! operations on the task list.
 //!
+//! Every function which changes the tasks of a tree notes what it did in the
+//! tree's journal as an [`Op`]. Tasks are named in it by uid rather than by
+//! index, so the operations can be replayed on a copy of the tree with
+//! [`replay`] even after the indices of the tasks have moved.
+//!
+//! [`Op`]: ./enum.Op.html
+//! [`replay`]: ./fn.replay.html
 //! [`Tree`]: ./struct.Tree.html
 
-use crate::task::Task;
+use crate::task::{self, Task};
 use crate::ToruError;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use time::PrimitiveDateTime;
 
 pub struct Children<'a> {
     current: usize,
@@ -27,10 +36,58 @@ impl<'a> Iterator for Children<'a> {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// The uid of the root until it is given one.
+pub const ROOT_UID: u64 = 0;
+/// The uid of the trash until it is given one.
+pub const TRASH_UID: u64 = 1;
+
+/// An operation made on a tree, naming tasks by their uids.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Op {
+    /// A task was added under `parent`.
+    Add {
+        parent: u64,
+        task: Task,
+    },
+    Complete {
+        uid: u64,
+    },
+    Trash {
+        uids: Vec<u64>,
+    },
+    Delete {
+        uids: Vec<u64>,
+    },
+    Restore {
+        uid: u64,
+    },
+    Move {
+        uid: u64,
+        parent: u64,
+    },
+    Tag {
+        uid: u64,
+        tag: String,
+    },
+    Due {
+        uid: u64,
+        due: PrimitiveDateTime,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Tree {
+    #[serde(default)]
     ptr: usize,
     tasks: Vec<Task>,
+    /// The index of the task holding deleted subtrees. Like the root it has no
+    /// parent, and it is only created once something is deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trash: Option<usize>,
+    /// The operations made on the tree since it was created or loaded.
+    #[serde(skip)]
+    journal: Vec<Op>,
 }
 
 impl Tree {
@@ -38,9 +95,30 @@ impl Tree {
         Self {
             ptr: 0,
             tasks: vec![Task::new()],
+            trash: None,
+            journal: Vec::new(),
+        }
+    }
+
+    /// Returns a copy of the tree with an empty journal.
+    pub fn snapshot(&self) -> Self {
+        Self {
+            ptr: self.ptr,
+            tasks: self.tasks.clone(),
+            trash: self.trash,
+            journal: Vec::new(),
         }
     }
 
+    /// Returns the operations made on the tree, oldest first.
+    pub fn journal(&self) -> &[Op] {
+        &self.journal
+    }
+
+    fn record(&mut self, op: Op) {
+        self.journal.push(op);
+    }
+
     pub fn ptr(&self) -> usize {
         self.ptr
     }
@@ -61,6 +139,22 @@ impl Tree {
         self.ptr == 0
     }
 
+    pub fn trash(&self) -> Option<usize> {
+        self.trash
+    }
+
+    pub fn set_trash(&mut self, trash: Option<usize>) {
+        self.trash = trash
+    }
+
+    /// Checks whether a task is the trash or somewhere below it.
+    pub fn in_trash(&self, id: usize) -> bool {
+        match self.trash {
+            Some(trash) => self.ancestry(id).first() == Some(&trash),
+            None => false,
+        }
+    }
+
     pub fn current(&self) -> &Task {
         &self.tasks[self.ptr]
     }
@@ -77,6 +171,37 @@ impl Tree {
         self.tasks.get(id).cloned()
     }
 
+    /// Returns the indices of the tasks leading from the root to the task at
+    /// `id`, both included.
+    pub fn ancestry(&self, id: usize) -> Vec<usize> {
+        let mut path = vec![id];
+        let mut task = self.task(id);
+
+        while let Some(parent) = task.and_then(|t| t.parent()) {
+            path.push(parent);
+            task = self.task(parent);
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// Returns the index of a task followed by the indices of all of its
+    /// descendants.
+    pub fn subtree(&self, id: usize) -> Vec<usize> {
+        let mut stack = vec![id];
+        let mut ptr = 0;
+
+        while ptr < stack.len() {
+            if let Some(task) = self.task(stack[ptr]) {
+                stack.extend(task.children().iter());
+            }
+            ptr += 1;
+        }
+
+        stack
+    }
+
     pub fn has_pending(&self, task: &Task) -> bool {
         self.children_of(task).any(|child| !child.is_complete())
     }
@@ -91,7 +216,7 @@ impl Tree {
         self
     }
 
-    pub fn children(&self) -> Children {
+    pub fn children(&self) -> Children<'_> {
         let parent = self.current();
         let children = parent.children();
         Children {
@@ -101,12 +226,12 @@ impl Tree {
         }
     }
 
-    pub fn children_of<'a>(&'a self, task: &'a Task) -> Children<'_> {
+    pub fn children_of<'a>(&'a self, task: &'a Task) -> Children<'a> {
         let children = task.children();
         Children {
             current: 0,
             indexes: children,
-            tasks: &self.tasks(),
+            tasks: self.tasks(),
         }
     }
 
@@ -128,13 +253,147 @@ impl Tree {
             .copied()
             .ok_or(ToruError::InvalidIndex(idx))
     }
+
+    /// Returns the uid of a task. The root and the trash have fixed uids until
+    /// they are given their own. A task from a file written before tasks had
+    /// uids is given one derived from the names of the tasks leading to it,
+    /// the same one each time the file is loaded until it is saved with it.
+    pub fn uid(&self, idx: usize) -> u64 {
+        if let Some(uid) = self.tasks[idx].uid() {
+            return uid;
+        }
+        if idx == 0 {
+            return ROOT_UID;
+        }
+        if self.trash == Some(idx) {
+            return TRASH_UID;
+        }
+
+        // FNV-1a, which unlike the standard library's hasher is the same in
+        // every build of toru. Siblings which share a name are told apart by
+        // how many come before them.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+
+        for pair in self.ancestry(idx).windows(2) {
+            let (parent, child) = (pair[0], pair[1]);
+            let name = self.tasks[child].name();
+            let before = self.tasks[parent]
+                .children()
+                .iter()
+                .take_while(|&&sibling| sibling != child)
+                .filter(|&&sibling| self.tasks[sibling].name() == name)
+                .count();
+
+            feed(name.as_bytes());
+            feed(&before.to_le_bytes());
+            feed(&[0]);
+        }
+
+        hash
+    }
+
+    /// Returns the index of the task with a uid.
+    pub fn find_uid(&self, uid: u64) -> Option<usize> {
+        (0..self.tasks.len()).find(|&idx| self.uid(idx) == uid)
+    }
+
+    /// Finds a task by the names of the tasks leading to it from the root,
+    /// separated by slashes, e.g. "Work/Report". An empty path is the root.
+    pub fn find(&self, path: &str) -> Option<usize> {
+        let mut idx = 0;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            idx = *self.task(idx)?.children().iter().find(|&&child| {
+                self.task(child).map(|task| task.name().as_str()) == Some(name)
+            })?;
+        }
+
+        Some(idx)
+    }
 }
 
+/// Gives every task which has no uid the one [`Tree::uid`] returns for it.
+///
+/// [`Tree::uid`]: ./struct.Tree.html#method.uid
+pub fn assign_uids(mut tree: Tree) -> Tree {
+    let uids: Vec<u64> =
+        (0..tree.tasks().len()).map(|idx| tree.uid(idx)).collect();
+    for (task, uid) in tree.tasks_mut().iter_mut().zip(uids) {
+        if task.uid().is_none() {
+            *task = task.clone().set_uid(Some(uid));
+        }
+    }
+
+    tree
+}
+
+/// Makes an operation from the journal of one tree on another, finding the
+/// tasks it names by their uids.
+pub fn replay(mut tree: Tree, op: Op) -> Result<Tree, String> {
+    fn find(tree: &Tree, uid: u64) -> Result<usize, String> {
+        tree.find_uid(uid)
+            .ok_or_else(|| format!("no task has the uid {:016x}", uid))
+    }
+    fn find_all(tree: &Tree, uids: &[u64]) -> Result<Vec<usize>, String> {
+        uids.iter().map(|&uid| find(tree, uid)).collect()
+    }
+
+    let tree = match op {
+        Op::Add { parent, task } => {
+            let ptr = tree.ptr();
+            tree.set_ptr(find(&tree, parent)?);
+            tree = add(tree, task);
+            tree.set_ptr(ptr);
+            tree
+        }
+        Op::Complete { uid } => {
+            let idx = find(&tree, uid)?;
+            complete(tree, idx)
+        }
+        Op::Trash { uids } => {
+            let indices = find_all(&tree, &uids)?;
+            trash_all(tree, &indices)
+        }
+        Op::Delete { uids } => {
+            let indices = find_all(&tree, &uids)?;
+            delete_all(tree, &indices)
+        }
+        Op::Restore { uid } => {
+            let idx = find(&tree, uid)?;
+            restore(tree, idx)
+        }
+        Op::Move { uid, parent } => {
+            let idx = find(&tree, uid)?;
+            let parent = find(&tree, parent)?;
+            relocate(tree, idx, parent)
+        }
+        Op::Tag { uid, tag: name } => {
+            let idx = find(&tree, uid)?;
+            tag(tree, idx, name)
+        }
+        Op::Due { uid, due } => {
+            let idx = find(&tree, uid)?;
+            set_due(tree, idx, due)
+        }
+    };
+
+    Ok(tree)
+}
+
+/// Adds a task under the current task, giving it a uid if it has none.
 pub fn add(mut tree: Tree, task: Task) -> Tree {
-    let task = task.set_parent(tree.ptr());
+    let uid = task.uid().unwrap_or_else(task::new_uid);
+    let task = task.set_parent(tree.ptr()).set_uid(Some(uid));
+    let parent = tree.uid(tree.ptr());
     let index_of_child = tree.tasks().len();
     let new_parent = tree.current_owned().add_child(index_of_child);
-    tree.tasks_mut().push(task);
+    tree.tasks_mut().push(task.clone());
+    tree.record(Op::Add { parent, task });
     tree.replace_current(new_parent)
 }
 
@@ -143,9 +402,10 @@ pub fn ascend(mut tree: Tree) -> Tree {
         return tree;
     }
 
+    // The trash has no parent and leads back to the root
     match tree.current().parent() {
         Some(parent) => tree.set_ptr(parent),
-        None => unreachable!(),
+        None => tree.set_ptr(0),
     }
 
     tree
@@ -173,70 +433,251 @@ pub fn complete(mut tree: Tree, idx: usize) -> Tree {
         ptr += 1;
     }
 
+    let uid = tree.uid(idx);
+    tree.record(Op::Complete { uid });
     tree
 }
 
-pub fn delete(mut tree: Tree, idx: usize) -> Tree {
-    if idx == 0 {
+/// Permanently removes a task and all of its descendants.
+pub fn delete(tree: Tree, idx: usize) -> Tree {
+    delete_all(tree, &[idx])
+}
+
+/// Permanently removes several tasks and all of their descendants. The
+/// remaining tasks are shifted down to fill the gaps and every index held by
+/// the tree is updated to match.
+pub fn delete_all(mut tree: Tree, indices: &[usize]) -> Tree {
+    let length = tree.tasks().len();
+    let mut doomed = vec![false; length];
+    let indices: Vec<usize> = indices
+        .iter()
+        .copied()
+        .filter(|&idx| idx != 0 && idx < length)
+        .collect();
+    if indices.is_empty() {
         return tree;
     }
+    let uids = indices.iter().map(|&idx| tree.uid(idx)).collect();
 
-    let mut stack = Vec::new();
-    stack.push(idx);
-
-    while !stack.is_empty() {
-        let child_index = match stack.last().cloned() {
-            Some(i) => i,
-            None => unreachable!(),
-        };
-        let child_task = match tree.task(child_index) {
-            Some(t) => t,
-            None => {
-                stack.pop();
-                continue;
-            }
-        };
+    for &idx in indices.iter() {
+        for child in tree.subtree(idx) {
+            doomed[child] = true;
+        }
 
-        let children = child_task.children();
+        if let Some(parent) = tree.task(idx).and_then(|t| t.parent()) {
+            let new_parent = match tree.task_owned(parent) {
+                Some(t) => t.remove_child(idx),
+                None => unreachable!(),
+            };
+            tree = tree.replace_task(parent, new_parent);
+        }
+    }
 
-        if !children.is_empty() {
-            stack.extend(children.iter());
-            continue;
+    let mut remap = vec![None; length];
+    let mut next = 0;
+    for (old, &is_doomed) in doomed.iter().enumerate() {
+        if !is_doomed {
+            remap[old] = Some(next);
+            next += 1;
         }
+    }
 
-        let mut parent_index = match tree.task(child_index) {
-            Some(t) => match t.parent() {
-                Some(p) => p,
+    // The pointer moves up to the closest surviving ancestor
+    let ptr = tree
+        .ancestry(tree.ptr())
+        .into_iter()
+        .rev()
+        .find_map(|idx| remap[idx])
+        .unwrap_or(0);
+
+    let tasks = std::mem::take(tree.tasks_mut());
+    *tree.tasks_mut() = tasks
+        .into_iter()
+        .zip(doomed.iter())
+        .filter(|(_, &is_doomed)| !is_doomed)
+        .map(|(task, _)| task.reindex(|i| remap.get(i).copied().flatten()))
+        .collect();
+
+    tree.set_ptr(ptr);
+    tree.trash = tree.trash.and_then(|t| remap[t]);
+
+    tree.record(Op::Delete { uids });
+    tree
+}
+
+/// Moves a task, along with its descendants, from its parent to the end of
+/// another task's children.
+fn reparent(tree: Tree, idx: usize, new_parent: usize) -> Tree {
+    let old_parent = tree.task(idx).and_then(|t| t.parent());
+    let mut tree = match old_parent {
+        Some(parent) => {
+            let old_parent = match tree.task_owned(parent) {
+                Some(t) => t.remove_child(idx),
                 None => unreachable!(),
-            },
-            None => unreachable!(),
-        };
-        let mut new_parent = match tree.task_owned(parent_index) {
-            Some(t) => t.remove_child(child_index),
-            None => unreachable!(),
-        };
+            };
+            tree.replace_task(parent, old_parent)
+        }
+        None => tree,
+    };
+
+    let task = match tree.task_owned(idx) {
+        Some(t) => t.set_parent(new_parent),
+        None => panic!("Invalid index access at {}", idx),
+    };
+    let parent = match tree.task_owned(new_parent) {
+        Some(t) => t.add_child(idx),
+        None => panic!("Invalid index access at {}", new_parent),
+    };
+
+    tree = tree.replace_task(idx, task);
+    tree.replace_task(new_parent, parent)
+}
+
+/// Moves a task and its descendants to the trash, from where they can be
+/// restored. Deleting a task that is already in the trash removes it for good.
+pub fn trash(tree: Tree, idx: usize) -> Tree {
+    trash_all(tree, &[idx])
+}
 
-        tree = tree.replace_task(parent_index, new_parent);
-        tree.tasks_mut().swap_remove(child_index);
+/// Moves several tasks to the trash. Those already in the trash are removed
+/// for good, after the others have been moved.
+pub fn trash_all(mut tree: Tree, indices: &[usize]) -> Tree {
+    let mut purge = Vec::new();
+    let mut trashed = Vec::new();
 
-        // Need to update parent of the task we just swapped
-        let index_to_replace = tree.tasks().len();
-        parent_index = match tree.task(child_index) {
-            Some(t) => t.parent().unwrap(),
+    for &idx in indices {
+        if idx == 0 || tree.trash() == Some(idx) || tree.task(idx).is_none() {
+            continue;
+        }
+
+        if tree.in_trash(idx) {
+            purge.push(idx);
+            continue;
+        }
+
+        trashed.push(tree.uid(idx));
+        let trash = match tree.trash() {
+            Some(trash) => trash,
             None => {
-                // If there is no task at the one we just deleted we deleted the last node and don't
-                // need to do anything else
-                stack.pop();
-                continue;
+                let trash = tree.tasks().len();
+                tree.tasks_mut()
+                    .push(Task::new().set_name(String::from("Trash")));
+                tree.trash = Some(trash);
+                trash
             }
         };
-        new_parent = match tree.task_owned(parent_index) {
-            Some(t) => t.replace_child(index_to_replace, child_index),
+
+        let parent = tree.task(idx).and_then(|t| t.parent());
+        let task = match tree.task_owned(idx) {
+            Some(t) => t.set_trashed_from(parent),
             None => unreachable!(),
         };
-        tree = tree.replace_task(parent_index, new_parent);
+        tree = tree.replace_task(idx, task);
 
-        stack.pop();
+        if tree.subtree(idx).contains(&tree.ptr()) {
+            tree.set_ptr(parent.unwrap_or(0));
+        }
+
+        tree = reparent(tree, idx, trash);
+    }
+
+    if !trashed.is_empty() {
+        tree.record(Op::Trash { uids: trashed });
+    }
+    delete_all(tree, &purge)
+}
+
+/// Moves a task out of the trash and back under the parent it was deleted
+/// from. If that parent no longer exists, or is in the trash itself, the task
+/// is restored to the root instead.
+pub fn restore(mut tree: Tree, idx: usize) -> Tree {
+    let trash = match tree.trash() {
+        Some(trash) => trash,
+        None => return tree,
+    };
+
+    let task = match tree.task_owned(idx) {
+        Some(t) if t.parent() == Some(trash) => t,
+        _ => return tree,
+    };
+
+    let target = match task.trashed_from() {
+        Some(parent)
+            if tree.task(parent).is_some() && !tree.in_trash(parent) =>
+        {
+            parent
+        }
+        _ => 0,
+    };
+
+    let uid = tree.uid(idx);
+    tree = tree.replace_task(idx, task.set_trashed_from(None));
+    tree = reparent(tree, idx, target);
+    tree.record(Op::Restore { uid });
+    tree
+}
+
+/// Permanently removes everything in the trash.
+pub fn empty_trash(tree: Tree) -> Tree {
+    match tree.trash() {
+        Some(trash) => {
+            let mut tree = delete(tree, trash);
+            tree.trash = None;
+            tree
+        }
+        None => tree,
+    }
+}
+
+/// Moves a task and its descendants under a new parent. Moving a task into
+/// its own subtree, into the trash, or moving the root or the trash itself is
+/// ignored.
+pub fn relocate(tree: Tree, idx: usize, new_parent: usize) -> Tree {
+    let movable = idx != 0
+        && tree.trash() != Some(idx)
+        && tree.task(idx).is_some()
+        && tree.task(new_parent).is_some()
+        && !tree.in_trash(new_parent)
+        && !tree.subtree(idx).contains(&new_parent);
+
+    if !movable || tree.task(idx).and_then(|t| t.parent()) == Some(new_parent) {
+        return tree;
+    }
+
+    let (uid, parent) = (tree.uid(idx), tree.uid(new_parent));
+    let mut tree = reparent(tree, idx, new_parent);
+    tree.record(Op::Move { uid, parent });
+    tree
+}
+
+pub fn tag(tree: Tree, idx: usize, tag: String) -> Tree {
+    let task = match tree.task_owned(idx) {
+        Some(task) => task.add_tag(tag.clone()),
+        None => return tree,
+    };
+
+    let uid = tree.uid(idx);
+    let mut tree = tree.replace_task(idx, task);
+    tree.record(Op::Tag { uid, tag });
+    tree
+}
+
+pub fn set_due(tree: Tree, idx: usize, due: PrimitiveDateTime) -> Tree {
+    let task = match tree.task_owned(idx) {
+        Some(task) => task.set_due(due),
+        None => return tree,
+    };
+
+    let uid = tree.uid(idx);
+    let mut tree = tree.replace_task(idx, task);
+    tree.record(Op::Due { uid, due });
+    tree
+}
+
+/// Moves the current task pointer to the trash, if there is one.
+pub fn goto_trash(mut tree: Tree) -> Tree {
+    if let Some(trash) = tree.trash() {
+        tree.set_ptr(trash);
     }
 
     tree
@@ -250,6 +691,62 @@ pub fn descend(mut tree: Tree, idx: usize) -> Tree {
     tree
 }
 
+/// Copies tasks of another tree along with their descendants under a task,
+/// returning the indices of the copies of `roots`.
+fn copy(
+    mut tree: Tree,
+    other: &Tree,
+    roots: &[usize],
+    under: usize,
+) -> (Tree, Vec<usize>) {
+    let ptr = tree.ptr();
+    let mut copies = Vec::new();
+    let mut queue: VecDeque<(usize, usize)> =
+        roots.iter().map(|&root| (root, under)).collect();
+
+    while let Some((idx, parent)) = queue.pop_front() {
+        let task = match other.task(idx) {
+            Some(task) => task,
+            None => continue,
+        };
+        // A copy is a task of its own
+        let copy = task
+            .clone()
+            .set_children(Vec::new())
+            .set_trashed_from(None)
+            .set_uid(None);
+        let copied = tree.tasks().len();
+
+        tree.set_ptr(parent);
+        tree = add(tree, copy);
+        queue.extend(task.children().iter().map(|&child| (child, copied)));
+        if parent == under {
+            copies.push(copied);
+        }
+    }
+
+    tree.set_ptr(ptr);
+    (tree, copies)
+}
+
+/// Copies the tasks of another tree under a task, leaving out the other
+/// tree's root and trash.
+pub fn graft(tree: Tree, other: &Tree, under: usize) -> Tree {
+    copy(tree, other, other.tasks()[0].children(), under).0
+}
+
+/// Copies the tasks in the trash of another tree to the trash, as if they had
+/// been deleted from under a task.
+pub fn graft_trash(tree: Tree, other: &Tree, under: usize) -> Tree {
+    let trashed = match other.trash().and_then(|trash| other.task(trash)) {
+        Some(trash) => trash.children(),
+        None => return tree,
+    };
+
+    let (tree, copies) = copy(tree, other, trashed, under);
+    trash_all(tree, &copies)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -297,4 +794,153 @@ mod test {
         tree = complete(tree, 1);
         assert!(tree.pending_children().next().is_none());
     }
+
+    #[test]
+    fn ancestry_of_nested_task() {
+        let tree = spawn_tree();
+
+        assert_eq!(tree.ancestry(0), vec![0]);
+        assert_eq!(tree.ancestry(9), vec![0, 1, 4, 9]);
+    }
+
+    #[test]
+    fn delete_keeps_indices_consistent() {
+        let mut tree = spawn_tree();
+        tree.set_ptr(4);
+        tree = delete(tree, 2);
+
+        // 4 was shifted down to 3 and its child 9 down to 6
+        assert_eq!(tree.ptr(), 3);
+        assert_eq!(tree.ancestry(6), vec![0, 1, 3, 6]);
+        assert_eq!(tree.current().children(), &vec![6]);
+    }
+
+    #[test]
+    fn trash_and_restore() {
+        let mut tree = spawn_tree();
+        tree.set_ptr(3);
+        tree = trash(tree, 3);
+
+        let bin = tree.trash().unwrap();
+        assert_eq!(tree.tasks().len(), 11);
+        assert_eq!(tree.ptr(), 1);
+        assert!(tree.in_trash(7));
+        assert!(!tree.current().has_child_with_index(3));
+
+        tree = restore(tree, 3);
+        assert!(!tree.in_trash(3));
+        assert_eq!(tree.ancestry(7), vec![0, 1, 3, 7]);
+        assert!(tree.task(bin).unwrap().children().is_empty());
+    }
+
+    #[test]
+    fn empty_trash_removes_subtrees() {
+        let mut tree = spawn_tree();
+        tree = trash(tree, 2);
+        tree = trash(tree, 9);
+        tree = empty_trash(tree);
+
+        assert_eq!(tree.trash(), None);
+        assert_eq!(tree.tasks().len(), 6);
+        assert_eq!(tree.ancestry(5), vec![0, 1, 2, 5]);
+    }
+
+    #[test]
+    fn relocate_rejects_cycles() {
+        let mut tree = spawn_tree();
+        tree = relocate(tree, 1, 7);
+        assert_eq!(tree.ancestry(7), vec![0, 1, 3, 7]);
+
+        tree = relocate(tree, 3, 2);
+        assert_eq!(tree.ancestry(7), vec![0, 2, 3, 7]);
+        assert_eq!(tree.task(1).unwrap().children(), &vec![4]);
+    }
+
+    #[test]
+    fn bulk_delete_in_trash() {
+        let mut tree = spawn_tree();
+        tree = trash_all(tree, &[3, 4]);
+        assert!(tree.in_trash(9));
+
+        tree = trash_all(tree, &[3, 4]);
+        assert_eq!(tree.tasks().len(), 6);
+        assert!(tree
+            .task(tree.trash().unwrap())
+            .unwrap()
+            .children()
+            .is_empty());
+    }
+
+    #[test]
+    fn journal_replays_on_a_copy() {
+        let tree = spawn_tree().snapshot();
+        let copy = tree.snapshot();
+        let due = crate::task::parse_due("2021-03-01").unwrap();
+
+        let mut tree = trash_all(tree, &[2, 9]);
+        tree = restore(tree, 2);
+        tree = relocate(tree, 3, 2);
+        tree = tag(tree, 7, String::from("work"));
+        tree = set_due(tree, 8, due);
+        tree = complete(tree, 3);
+        tree = delete(tree, 4);
+        tree = empty_trash(tree);
+        tree = add(tree, Task::new());
+        assert_eq!(tree.journal().len(), 9);
+
+        let replayed = tree
+            .journal()
+            .iter()
+            .cloned()
+            .try_fold(copy, replay)
+            .unwrap();
+        assert_eq!(replayed.tasks(), tree.tasks());
+        assert_eq!(replayed.trash(), tree.trash());
+    }
+
+    #[test]
+    fn find_by_names() {
+        let mut tree = Tree::new();
+        tree = add(tree, Task::new().set_name(String::from("Work")));
+        tree.set_ptr(1);
+        tree = add(tree, Task::new().set_name(String::from("Report")));
+
+        assert_eq!(tree.find(""), Some(0));
+        assert_eq!(tree.find("Work/Report"), Some(2));
+        assert_eq!(tree.find("/Work/"), Some(1));
+        assert_eq!(tree.find("Report"), None);
+    }
+
+    #[test]
+    fn graft_copies_tasks() {
+        let mut other = spawn_tree();
+        other = trash(other, 4);
+
+        let mut tree = add(Tree::new(), Task::new());
+        tree = graft(tree, &other, 1);
+
+        assert_eq!(tree.ptr(), 0);
+        assert_eq!(tree.trash(), None);
+        // Everything below the other root apart from the trashed subtree
+        assert_eq!(tree.tasks().len(), 2 + 7);
+        assert_eq!(tree.ancestry(8), vec![0, 1, 2, 4, 8]);
+        assert_eq!(tree.task(3).unwrap().children(), &vec![5, 6]);
+    }
+
+    #[test]
+    fn graft_trash_keeps_tasks_deleted() {
+        let mut other = spawn_tree();
+        other = trash(other, 4);
+
+        let mut tree = add(Tree::new(), Task::new());
+        tree = graft_trash(tree, &other, 1);
+
+        let trash = tree.trash().unwrap();
+        assert_eq!(tree.task(trash).unwrap().children(), &vec![2]);
+        assert_eq!(tree.task(2).unwrap().trashed_from(), Some(1));
+        assert_eq!(tree.task(2).unwrap().children(), &vec![3]);
+
+        tree = restore(tree, 2);
+        assert_eq!(tree.ancestry(3), vec![0, 1, 2, 3]);
+    }
 }
@@ -1,62 +1,122 @@
-use crate::task::Task;
+pub mod keymap;
+mod screen;
+
+use crate::storage::Storage;
+use crate::task::{self, Task};
 use crate::tree::{self, Tree};
 use crate::Result;
+use keymap::{Keymap, Operation};
+use screen::Screen;
+use signal_hook::{
+    consts::{SIGHUP, SIGTERM, SIGWINCH},
+    iterator::Signals,
+};
 
 use std::{
-    fmt,
+    collections::BTreeSet,
     io::{self, Write},
-    sync::mpsc::{self, Receiver},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use termion::{
-    clear, cursor,
-    event::{self, Key},
-    input::TermRead,
+    cursor,
+    event::{self, Key, MouseButton, MouseEvent},
+    input::{MouseTerminal, TermRead},
     raw::IntoRawMode,
     screen::AlternateScreen,
-    terminal_size,
+    style, terminal_size,
 };
 
-const RESIZE_POLL_TIMEOUT: Duration = Duration::from_millis(150);
+/// The terminal size assumed when it cannot be queried.
+const FALLBACK_SIZE: (u16, u16) = (80, 24);
+/// Two clicks on the same row within this interval count as a double click.
+const DOUBLE_CLICK_TIMEOUT: Duration = Duration::from_millis(400);
+const BREADCRUMB_SEPARATOR: &str = " / ";
+/// How long a completed task stays crossed out in the list before it is
+/// removed.
+const FADE_DURATION: Duration = Duration::from_millis(800);
+/// How often the screen is redrawn while completed tasks are fading out.
+const FADE_TICK: Duration = Duration::from_millis(100);
 
 #[derive(Debug, PartialEq)]
 enum Event {
     Resize(u16, u16),
     Key(event::Key),
+    Mouse(event::MouseEvent),
+    /// Sent in place of an event when one is not received in time.
+    Tick,
+    /// The process was asked to exit, e.g. by SIGTERM or by the terminal
+    /// hanging up.
+    Terminate,
 }
 
 enum State {
     Normal,
-    Input,
+    Input(Action),
     Mutate(Action),
     Exit,
 }
 
+#[derive(Clone, Copy)]
 enum Action {
     AddTask,
-    DeleteTask,
+    DeleteTasks,
+    RestoreTasks,
+    EmptyTrash,
+    CompleteTasks,
+    TagTasks,
+    SetDue,
+    MoveTasks,
 }
 
 struct List {
     index: usize,
-    title: String,
-    items: Vec<String>,
+    offset: usize,
+    height: usize,
+    /// The index and name of every task from the root to the current one.
+    path: Vec<(usize, String)>,
+    /// The task index and text of every item.
+    items: Vec<(usize, String)>,
+    /// The task indices of the items marked for a bulk operation.
+    marked: BTreeSet<usize>,
+    /// Where a visual selection was started, if one is in progress.
+    anchor: Option<usize>,
+    /// Completed tasks which are still shown, and when they were completed.
+    fading: Vec<(usize, Instant)>,
 }
 
 impl List {
-    fn new(title: String, items: Vec<String>) -> Self {
+    fn new(
+        path: Vec<(usize, String)>,
+        items: Vec<(usize, String)>,
+        height: usize,
+    ) -> Self {
         Self {
             index: 0,
-            title,
+            offset: 0,
+            height,
+            path,
             items,
+            marked: BTreeSet::new(),
+            anchor: None,
+            fading: Vec::new(),
         }
     }
 
+    fn from_tree(tree: &Tree, height: usize) -> Self {
+        Self::new(breadcrumbs(tree), list_items(tree, &[]), height)
+    }
+
     fn rebuild(&mut self, tree: &Tree) {
-        let title = tree.current().name().clone();
-        let items: Vec<String> =
-            tree.pending_children().map(|t| t.name().clone()).collect();
+        let path = breadcrumbs(tree);
+        if path.last() != self.path.last() {
+            self.fading.clear();
+        }
+
+        let fading: Vec<usize> =
+            self.fading.iter().map(|(id, _)| *id).collect();
+        let items = list_items(tree, &fading);
         let length = items.len();
 
         if length == 0 {
@@ -65,8 +125,132 @@ impl List {
             self.index = length - 1;
         }
 
-        self.title = title;
+        // Marks only make sense for the level they were made on
+        if path.last() != self.path.last() {
+            self.clear_marks();
+        }
+        self.marked
+            .retain(|id| items.iter().any(|(item, _)| item == id));
+        if self.anchor.is_some_and(|anchor| anchor >= length) {
+            self.anchor = None;
+        }
+
+        self.path = path;
         self.items = items;
+        self.scroll();
+    }
+
+    /// Returns the task index of the selected item.
+    fn selected(&self) -> Option<usize> {
+        self.items.get(self.index).map(|(id, _)| *id)
+    }
+
+    /// Keeps just completed tasks in the list for a moment so that they can be
+    /// shown crossed out.
+    fn fade(&mut self, ids: &[usize]) {
+        let now = Instant::now();
+        self.fading.extend(ids.iter().map(|&id| (id, now)));
+    }
+
+    /// Forgets the tasks which have been fading for long enough. Returns
+    /// whether there were any.
+    fn expire(&mut self, now: Instant) -> bool {
+        let length = self.fading.len();
+        self.fading
+            .retain(|(_, at)| now.duration_since(*at) < FADE_DURATION);
+        length != self.fading.len()
+    }
+
+    /// Returns how far along a task is in fading out, if it is.
+    fn fade_progress(&self, id: usize) -> Option<f32> {
+        self.fading
+            .iter()
+            .find(|(item, _)| *item == id)
+            .map(|(_, at)| {
+                at.elapsed().as_secs_f32() / FADE_DURATION.as_secs_f32()
+            })
+    }
+
+    /// Marks or unmarks the selected item and moves on to the next one.
+    fn toggle_mark(&mut self) {
+        if let Some(&(id, _)) = self.items.get(self.index) {
+            if !self.marked.remove(&id) {
+                self.marked.insert(id);
+            }
+        }
+        self.increment();
+    }
+
+    /// Starts a visual selection at the selected item, or marks every item in
+    /// the one in progress.
+    fn visual(&mut self) {
+        match self.anchor {
+            Some(_) => {
+                let ids: Vec<usize> =
+                    self.in_visual().map(|(id, _)| *id).collect();
+                self.marked.extend(ids);
+                self.anchor = None;
+            }
+            None if !self.items.is_empty() => self.anchor = Some(self.index),
+            None => {}
+        }
+    }
+
+    fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.anchor = None;
+    }
+
+    fn in_visual(&self) -> impl Iterator<Item = &(usize, String)> {
+        let (start, end) = match self.anchor {
+            Some(anchor) => {
+                (anchor.min(self.index), anchor.max(self.index) + 1)
+            }
+            None => (0, 0),
+        };
+
+        self.items.iter().take(end).skip(start)
+    }
+
+    fn is_marked(&self, position: usize) -> bool {
+        let in_visual = match self.anchor {
+            Some(anchor) => {
+                anchor.min(self.index) <= position
+                    && position <= anchor.max(self.index)
+            }
+            None => false,
+        };
+
+        in_visual
+            || self
+                .items
+                .get(position)
+                .is_some_and(|(id, _)| self.marked.contains(id))
+    }
+
+    /// Returns the task indices a bulk operation applies to: the marked items
+    /// in list order, or the selected item when nothing is marked.
+    fn selection(&self) -> Vec<usize> {
+        let marked: Vec<usize> = (0..self.items.len())
+            .filter(|&position| self.is_marked(position))
+            .map(|position| self.items[position].0)
+            .collect();
+
+        if !marked.is_empty() {
+            return marked;
+        }
+
+        self.items
+            .get(self.index)
+            .map(|(id, _)| *id)
+            .into_iter()
+            .collect()
+    }
+
+    /// Updates the number of rows available for items after a terminal resize.
+    fn resize(&mut self, height: usize) {
+        self.height = height;
+        self.scroll();
     }
 
     fn increment(&mut self) {
@@ -75,186 +259,632 @@ impl List {
         if 0 < length && self.index < length - 1 {
             self.index += 1;
         }
+        self.scroll();
     }
 
     fn decrement(&mut self) {
         if self.index > 0 {
             self.index -= 1;
         }
+        self.scroll();
     }
-}
 
-impl fmt::Display for List {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}\r\n", self.title)?;
-        for (idx, item) in self.items.iter().enumerate() {
-            let prompt = if self.index == idx { ">" } else { " " };
-            write!(f, "{}. {} {}\r\n", idx + 1, prompt, item)?;
+    fn page_down(&mut self) {
+        let length = self.items.len();
+
+        if length > 0 {
+            self.index = (self.index + self.page()).min(length - 1);
         }
+        self.scroll();
+    }
 
-        Ok(())
+    fn page_up(&mut self) {
+        self.index = self.index.saturating_sub(self.page());
+        self.scroll();
+    }
+
+    fn first(&mut self) {
+        self.index = 0;
+        self.scroll();
+    }
+
+    fn last(&mut self) {
+        self.index = self.items.len().saturating_sub(1);
+        self.scroll();
+    }
+
+    /// Selects the item at a position in the list.
+    fn select(&mut self, index: usize) {
+        if index < self.items.len() {
+            self.index = index;
+        }
+        self.scroll();
+    }
+
+    fn page(&self) -> usize {
+        self.height.max(1)
+    }
+
+    /// Moves the viewport so that the selection is always visible, and so that
+    /// no rows are wasted below the last item.
+    fn scroll(&mut self) {
+        let height = self.page();
+
+        if self.index < self.offset {
+            self.offset = self.index;
+        } else if self.index >= self.offset + height {
+            self.offset = self.index + 1 - height;
+        }
+
+        let max_offset = self.items.len().saturating_sub(height);
+        if self.offset > max_offset {
+            self.offset = max_offset;
+        }
+    }
+
+    fn visible(&self) -> impl Iterator<Item = (usize, &(usize, String))> {
+        self.items
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(self.page())
+    }
+
+    /// Returns the position in the list of the item drawn on a zero based
+    /// screen row.
+    fn item_at(&self, row: usize) -> Option<usize> {
+        let index = self.offset + row.checked_sub(1)?;
+
+        if row <= self.page() && index < self.items.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the task index of the breadcrumb drawn at a zero based column
+    /// of the title row.
+    fn crumb_at(&self, column: usize) -> Option<usize> {
+        let mut start = 0;
+
+        for (idx, name) in self.path.iter() {
+            let end = start + name.chars().count();
+            if start <= column && column < end {
+                return Some(*idx);
+            }
+            start = end + BREADCRUMB_SEPARATOR.len();
+        }
+
+        None
+    }
+
+    /// Draws the breadcrumbs on the first row of the screen followed by the
+    /// visible items.
+    fn draw(&self, screen: &mut Screen) {
+        let length = self.items.len();
+        let names: Vec<&str> =
+            self.path.iter().map(|(_, name)| name.as_str()).collect();
+        let mut title = names.join(BREADCRUMB_SEPARATOR);
+
+        if length > self.page() {
+            let last = (self.offset + self.page()).min(length);
+            let above = if self.offset > 0 { "\u{2191}" } else { " " };
+            let below = if last < length { "\u{2193}" } else { " " };
+            title.push_str(&format!(
+                " [{}-{}/{}] {}{}",
+                self.offset + 1,
+                last,
+                length,
+                above,
+                below
+            ));
+        }
+        screen.set_line(0, &title);
+
+        for (row, (idx, (id, item))) in self.visible().enumerate() {
+            let prompt = if self.index == idx { ">" } else { " " };
+            let mark = if self.is_marked(idx) { " *" } else { "" };
+            let line = format!("{}. {}{} {}", idx + 1, prompt, mark, item);
+
+            match self.fade_progress(*id) {
+                Some(progress) if progress < 0.5 => screen.set_styled_line(
+                    row + 1,
+                    &line,
+                    style::CrossedOut.as_ref(),
+                ),
+                Some(_) => screen.set_styled_line(
+                    row + 1,
+                    &line,
+                    &format!("{}{}", style::CrossedOut, style::Faint),
+                ),
+                None => screen.set_line(row + 1, &line),
+            }
+        }
     }
 }
 
 struct App<W: Write> {
     output: W,
+    screen: Screen,
     cursor_offset: usize,
     list: List,
+    keymap: Keymap,
+    /// When and on which row the left mouse button was last pressed.
+    last_click: Option<(Instant, u16)>,
+    /// The prompt shown on the last row while text is being entered or a
+    /// question is being asked.
+    prompt: Option<String>,
+    /// A message shown on the last row until the next key is pressed.
+    status: Option<String>,
+    /// The tasks cut for moving, waiting to be pasted under another task.
+    clipboard: Vec<usize>,
     buffer: String,
+    /// Where the tree is saved after every change.
+    file: Box<dyn Storage>,
+    /// Set when the tree has changed since it was last saved.
+    unsaved: bool,
+    /// The current task when the tree was last saved.
+    saved_ptr: usize,
+    /// Set once a terminate event is received so that every state unwinds.
+    terminated: bool,
 }
 
 impl<W: Write> App<W> {
-    fn new(output: W, title: String, items: Vec<String>) -> Self {
+    fn new(
+        output: W,
+        tree: &Tree,
+        keymap: Keymap,
+        size: (u16, u16),
+        file: Box<dyn Storage>,
+    ) -> Self {
+        let (cols, rows) = size;
         Self {
             output,
+            screen: Screen::new(cols, rows),
             cursor_offset: 0,
-            list: List::new(title, items),
+            list: List::from_tree(tree, list_height(rows)),
+            keymap,
+            last_click: None,
+            prompt: None,
+            status: None,
+            clipboard: Vec::new(),
             buffer: String::with_capacity(40),
+            file,
+            unsaved: false,
+            saved_ptr: tree.ptr(),
+            terminated: false,
+        }
+    }
+
+    fn resize(&mut self, cols: u16, rows: u16) {
+        self.screen.resize(cols, rows);
+        self.list.resize(list_height(rows));
+    }
+
+    /// Returns the byte index in the buffer of the cursor, which
+    /// `cursor_offset` counts in characters from the end.
+    fn cursor_index(&self) -> usize {
+        let length = self.buffer.chars().count();
+        self.buffer
+            .char_indices()
+            .nth(length - self.cursor_offset)
+            .map_or(self.buffer.len(), |(index, _)| index)
+    }
+
+    fn save(&mut self, tree: &Tree) -> Result<()> {
+        self.file.save(tree)?;
+        self.unsaved = false;
+        self.saved_ptr = tree.ptr();
+        Ok(())
+    }
+
+    /// Saves the tree if it changed. A failed save is reported on the status
+    /// line rather than ending the session, so it can be retried by the next
+    /// change.
+    fn autosave(&mut self, tree: &Tree) {
+        if self.unsaved {
+            if let Err(e) = self.save(tree) {
+                self.status = Some(format!("Autosave failed: {}", e));
+            }
         }
     }
 }
 
-pub fn run(mut tree: Tree) -> Result<Tree> {
+/// Runs the TUI until the user quits or the process is asked to terminate.
+/// The tree is saved to the file after every change and once more on exit.
+pub fn run(tree: Tree, keymap: Keymap, file: Box<dyn Storage>) -> Result<()> {
     // Set up the channel
     let rx = spawn_event_threads();
-    let output = AlternateScreen::from(io::stdout().into_raw_mode()?);
+    let output = MouseTerminal::from(AlternateScreen::from(
+        io::stdout().into_raw_mode()?,
+    ));
 
     let mut state = State::Normal;
-    let title = tree.task(tree.ptr()).unwrap().name().clone();
-    let items = tree.pending_children().map(|t| t.name().clone()).collect();
-    let mut app = App::new(output, title, items);
+    let size = terminal_size().unwrap_or(FALLBACK_SIZE);
+    let mut app = App::new(output, &tree, keymap, size, file);
 
-    let output = &mut app.output;
+    redraw(&mut app)?;
+    normal_state(&rx, &mut state, app, tree)?;
 
-    write!(output, "{}\r{}{}", clear::All, app.list, cursor::Hide)?;
-    output.flush()?;
-
-    tree = normal_state(&rx, &mut state, app, tree)?;
-
-    Ok(tree)
+    // Dropping the terminal leaves the alternate screen and raw mode
+    Ok(())
 }
 
 fn spawn_event_threads() -> Receiver<Event> {
     let (tx, rx) = mpsc::channel::<Event>();
     let txc = tx.clone();
 
-    // This thread only sends termion key events
+    // This thread only sends termion key and mouse events
     thread::spawn(move || {
         for event in io::stdin().events() {
-            if let Ok(event::Event::Key(key)) = event {
-                txc.send(Event::Key(key)).unwrap();
+            match event {
+                Ok(event::Event::Key(key)) => {
+                    txc.send(Event::Key(key)).unwrap();
+                }
+                Ok(event::Event::Mouse(mouse)) => {
+                    txc.send(Event::Mouse(mouse)).unwrap();
+                }
+                _ => {}
             }
         }
     });
 
-    // This thread polls for terminal resize events
-    thread::spawn(move || {
-        let (mut previous_x, mut previous_y) = match terminal_size() {
-            Ok(a) => a,
-            _ => unreachable!(),
-        };
+    // This thread reports the new terminal size whenever the terminal is
+    // resized, and asks the TUI to save and exit on SIGTERM or SIGHUP.
+    if let Ok(mut signals) = Signals::new([SIGWINCH, SIGTERM, SIGHUP]) {
+        thread::spawn(move || {
+            for signal in signals.forever() {
+                let event = match signal {
+                    SIGWINCH => match terminal_size() {
+                        Ok((x, y)) => Event::Resize(x, y),
+                        Err(_) => continue,
+                    },
+                    _ => Event::Terminate,
+                };
 
-        loop {
-            let (current_x, current_y) = match terminal_size() {
-                Ok(a) => a,
-                _ => unreachable!(),
-            };
-            if current_x != previous_x || current_y != previous_y {
-                previous_x = current_x;
-                previous_y = current_y;
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    rx
+}
 
-                tx.send(Event::Resize(current_x, current_y)).unwrap();
+/// The number of rows left for list items once the title and the prompt line
+/// have been accounted for.
+fn list_height(rows: u16) -> usize {
+    (rows as usize).saturating_sub(2)
+}
+
+/// Builds the items for the current task's pending children, along with any
+/// completed children listed in `keep`.
+fn list_items(tree: &Tree, keep: &[usize]) -> Vec<(usize, String)> {
+    tree.current()
+        .children()
+        .iter()
+        .filter_map(|&idx| {
+            let task = tree.task(idx)?;
+            if task.is_complete() && !keep.contains(&idx) {
+                return None;
             }
 
-            thread::sleep(RESIZE_POLL_TIMEOUT);
+            let mut text = task.name().clone();
+            for tag in task.tags() {
+                text.push_str(&format!(" #{}", tag));
+            }
+
+            Some((idx, text))
+        })
+        .collect()
+}
+
+fn breadcrumbs(tree: &Tree) -> Vec<(usize, String)> {
+    tree.ancestry(tree.ptr())
+        .into_iter()
+        .filter_map(|idx| Some((idx, tree.task(idx)?.name().clone())))
+        .collect()
+}
+
+/// Descends into the selected child, if there is one.
+fn descend_selected<W: Write>(app: &mut App<W>, tree: Tree) -> Tree {
+    match app.list.selected() {
+        Some(child) => {
+            let tree = tree::descend(tree, child);
+            app.list.rebuild(&tree);
+            tree
         }
-    });
+        None => tree,
+    }
+}
 
-    rx
+/// Handles a mouse event while in the normal state.
+fn mouse_event<W: Write>(
+    app: &mut App<W>,
+    mut tree: Tree,
+    mouse: MouseEvent,
+) -> Tree {
+    match mouse {
+        MouseEvent::Press(MouseButton::WheelUp, _, _) => app.list.decrement(),
+        MouseEvent::Press(MouseButton::WheelDown, _, _) => app.list.increment(),
+        MouseEvent::Press(MouseButton::Left, x, y) => {
+            // Coordinates start at 1, but termion reports 0 for positions
+            // it cannot decode
+            let (column, row) = match (
+                (x as usize).checked_sub(1),
+                (y as usize).checked_sub(1),
+            ) {
+                (Some(column), Some(row)) => (column, row),
+                _ => return tree,
+            };
+
+            if row == 0 {
+                if let Some(idx) = app.list.crumb_at(column) {
+                    tree.set_ptr(idx);
+                    app.list.rebuild(&tree);
+                }
+                app.last_click = None;
+            } else if let Some(index) = app.list.item_at(row) {
+                let now = Instant::now();
+                let double_click = match app.last_click {
+                    Some((at, last_y)) => {
+                        last_y == y
+                            && now.duration_since(at) < DOUBLE_CLICK_TIMEOUT
+                    }
+                    None => false,
+                };
+
+                app.list.select(index);
+                if double_click {
+                    app.last_click = None;
+                    tree = descend_selected(app, tree);
+                } else {
+                    app.last_click = Some((now, y));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    tree
 }
 
-fn redraw<W: Write>(output: &mut W, list: &List) -> Result<()> {
-    write!(
-        output,
-        "{}{}{}{}{}",
-        cursor::Save,
-        clear::All,
-        cursor::Goto(1, 1),
-        list,
-        cursor::Restore
-    )?;
-    output.flush()?;
+/// Composes the next frame and writes whatever changed since the last one.
+fn redraw<W: Write>(app: &mut App<W>) -> Result<()> {
+    let screen = &mut app.screen;
+    screen.clear();
+    app.list.draw(screen);
+
+    if let Some(prompt) = &app.prompt {
+        let row = screen.height().saturating_sub(1);
+        let line = format!("{}{}", prompt, app.buffer);
+        let column = line.chars().count() - app.cursor_offset;
 
+        screen.set_line(row as usize, &line);
+        screen.set_cursor(column.min(screen.width() as usize) as u16, row);
+    } else if let Some(status) = &app.status {
+        let row = screen.height().saturating_sub(1);
+        screen.set_line(row as usize, status);
+    }
+
+    screen.flush(&mut app.output)?;
     Ok(())
 }
 
+/// Waits for the next event. While completed tasks are fading out a tick is
+/// returned at regular intervals so that the list can be animated.
+fn next_event(rx: &Receiver<Event>, list: &List) -> Option<Event> {
+    if list.fading.is_empty() {
+        return rx.recv().ok();
+    }
+
+    match rx.recv_timeout(FADE_TICK) {
+        Ok(event) => Some(event),
+        Err(RecvTimeoutError::Timeout) => Some(Event::Tick),
+        Err(RecvTimeoutError::Disconnected) => None,
+    }
+}
+
 fn normal_state<W: Write>(
     rx: &Receiver<Event>,
     state: &mut State,
     mut app: App<W>,
     mut tree: Tree,
 ) -> Result<Tree> {
-    for received in rx {
-        match received {
-            Event::Resize(_x, _y) => {
-                redraw(&mut app.output, &app.list)?;
+    // The keys of a binding such as 'gg' which has only been partly typed
+    let mut pending = Vec::new();
+
+    while let Some(received) = next_event(rx, &app.list) {
+        let operation = match received {
+            Event::Resize(x, y) => {
+                app.resize(x, y);
+                None
             }
-            Event::Key(key) => match key {
-                Key::Left | Key::Char('h') => {
-                    tree = tree::ascend(tree);
-                    app.list.rebuild(&tree);
-                }
-                Key::Char('~') => {
-                    tree.set_ptr(0);
+            Event::Tick => {
+                if app.list.expire(Instant::now()) {
                     app.list.rebuild(&tree);
                 }
-                Key::Right | Key::Char('l') => {
-                    let index = app.list.index;
-                    let selected_child = match tree.nth_child(index) {
-                        Ok(child) => child,
-                        Err(_) => continue,
-                    };
-                    tree = tree::descend(tree, selected_child);
-                    app.list.rebuild(&tree);
-                }
-                Key::Up | Key::Char('k') => {
-                    app.list.decrement();
-                }
-                Key::Down | Key::Char('j') => {
-                    app.list.increment();
-                }
-                Key::Char('d') => {
-                    *state = State::Mutate(Action::DeleteTask);
-                    tree = mutate_state(state, &mut app, tree);
-                    app.list.rebuild(&tree);
-                }
-                Key::Char('i') => {
-                    let (_cols, rows) = terminal_size()?;
-                    write!(
-                        &mut app.output,
-                        "{}{}",
-                        cursor::Goto(1, rows),
-                        cursor::Show
-                    )?;
-                    app.output.flush()?;
-                    *state = State::Input;
-                    tree = input_state("Name:", &rx, state, &mut app, tree)?;
+                None
+            }
+            Event::Mouse(mouse) => {
+                tree = mouse_event(&mut app, tree, mouse);
+                None
+            }
+            Event::Key(key) => {
+                app.status = None;
+                app.keymap.next(&mut pending, key)
+            }
+            Event::Terminate => break,
+        };
+
+        match operation {
+            Some(Operation::Ascend) => {
+                tree = tree::ascend(tree);
+                app.list.rebuild(&tree);
+            }
+            Some(Operation::Root) => {
+                tree.set_ptr(0);
+                app.list.rebuild(&tree);
+            }
+            Some(Operation::Descend) => {
+                tree = descend_selected(&mut app, tree);
+            }
+            Some(Operation::Previous) => app.list.decrement(),
+            Some(Operation::Next) => app.list.increment(),
+            Some(Operation::PageUp) => app.list.page_up(),
+            Some(Operation::PageDown) => app.list.page_down(),
+            Some(Operation::First) => app.list.first(),
+            Some(Operation::Last) => app.list.last(),
+            Some(Operation::Delete) => {
+                if let Some(question) = delete_question(&app, &tree) {
+                    if confirm_state(&question, rx, &mut app)? {
+                        *state = State::Mutate(Action::DeleteTasks);
+                        tree = mutate_state(state, &mut app, tree);
+                        app.list.rebuild(&tree);
+                    }
                 }
-                Key::Char('q') => {
-                    *state = State::Exit;
-                    break;
+            }
+            Some(Operation::ToggleMark) => app.list.toggle_mark(),
+            Some(Operation::Visual) => app.list.visual(),
+            Some(Operation::ClearMarks) => app.list.clear_marks(),
+            Some(Operation::Complete) => {
+                *state = State::Mutate(Action::CompleteTasks);
+                tree = mutate_state(state, &mut app, tree);
+                app.list.rebuild(&tree);
+            }
+            Some(Operation::Tag) => {
+                *state = State::Input(Action::TagTasks);
+                tree = input_state("Tag:", rx, state, &mut app, tree)?;
+            }
+            Some(Operation::Due) => {
+                *state = State::Input(Action::SetDue);
+                tree = input_state("Due:", rx, state, &mut app, tree)?;
+            }
+            Some(Operation::Cut) => {
+                app.clipboard = app.list.selection();
+                app.list.clear_marks();
+                app.status = Some(format!(
+                    "{} task(s) cut, paste them under another task to move",
+                    app.clipboard.len()
+                ));
+            }
+            Some(Operation::Paste) => {
+                *state = State::Mutate(Action::MoveTasks);
+                tree = mutate_state(state, &mut app, tree);
+                app.list.rebuild(&tree);
+            }
+            Some(Operation::Trash) => {
+                tree = tree::goto_trash(tree);
+                app.list.rebuild(&tree);
+            }
+            Some(Operation::Restore) => {
+                *state = State::Mutate(Action::RestoreTasks);
+                tree = mutate_state(state, &mut app, tree);
+                app.list.rebuild(&tree);
+            }
+            Some(Operation::EmptyTrash) => {
+                if let Some(trash) = tree.trash() {
+                    let count = tree.subtree(trash).len() - 1;
+                    let question = format!(
+                        "Permanently delete {} task(s) in the trash? [y/N]",
+                        count
+                    );
+                    if confirm_state(&question, rx, &mut app)? {
+                        *state = State::Mutate(Action::EmptyTrash);
+                        tree = mutate_state(state, &mut app, tree);
+                        app.list.rebuild(&tree);
+                    }
                 }
-                _ => {}
-            },
+            }
+            Some(Operation::Add) => {
+                *state = State::Input(Action::AddTask);
+                tree = input_state("Name:", rx, state, &mut app, tree)?;
+            }
+            Some(Operation::Quit) => {
+                *state = State::Exit;
+                break;
+            }
+            None => {}
+        }
+
+        if app.terminated {
+            break;
         }
 
-        redraw(&mut app.output, &app.list)?;
+        app.autosave(&tree);
+        redraw(&mut app)?;
     }
 
-    write!(&mut app.output, "{}", cursor::Show)?;
+    if app.unsaved || tree.ptr() != app.saved_ptr {
+        app.save(&tree)?;
+    }
+
+    // The terminal may already be gone after a hang up
+    let _ = write!(&mut app.output, "{}", cursor::Show);
     Ok(tree)
 }
 
+/// Builds the question asked before deleting the selection, or returns `None`
+/// if there is nothing to delete.
+fn delete_question<W: Write>(app: &App<W>, tree: &Tree) -> Option<String> {
+    let selection = app.list.selection();
+    let first = *selection.first()?;
+    let descendants: usize = selection
+        .iter()
+        .map(|&idx| tree.subtree(idx).len() - 1)
+        .sum();
+
+    let verb = if tree.in_trash(first) {
+        "Permanently delete"
+    } else {
+        "Delete"
+    };
+    let target = match selection.len() {
+        1 => format!("'{}'", tree.task(first)?.name()),
+        n => format!("{} tasks", n),
+    };
+    let subtasks = match descendants {
+        0 => String::new(),
+        1 => String::from(" and 1 subtask"),
+        n => format!(" and {} subtasks", n),
+    };
+
+    Some(format!("{} {}{}? [y/N]", verb, target, subtasks))
+}
+
+/// Asks a yes or no question on the prompt line. Anything but 'y' is a no.
+fn confirm_state<W: Write>(
+    question: &str,
+    rx: &Receiver<Event>,
+    app: &mut App<W>,
+) -> Result<bool> {
+    app.prompt = Some(format!("{} ", question));
+    redraw(app)?;
+
+    let mut answer = false;
+    for received in rx {
+        match received {
+            Event::Resize(x, y) => {
+                app.resize(x, y);
+                redraw(app)?;
+            }
+            Event::Mouse(_) | Event::Tick => {}
+            Event::Key(key) => {
+                answer = key == Key::Char('y') || key == Key::Char('Y');
+                break;
+            }
+            Event::Terminate => {
+                app.terminated = true;
+                break;
+            }
+        }
+    }
+
+    app.prompt = None;
+    Ok(answer)
+}
+
 fn input_state<W: Write>(
     prompt: &str,
     rx: &Receiver<Event>,
@@ -262,28 +892,18 @@ fn input_state<W: Write>(
     app: &mut App<W>,
     mut tree: Tree,
 ) -> Result<Tree> {
-    write!(app.output, "{}", prompt)?;
-    app.output.flush()?;
+    app.prompt = Some(prompt.to_string());
+    redraw(app)?;
 
     for received in rx {
         match received {
-            Event::Resize(_new_x, new_y) => {
-                redraw(&mut app.output, &app.list)?;
-                write!(
-                    app.output,
-                    "{}{}{}{}",
-                    clear::CurrentLine,
-                    cursor::Goto(1, new_y),
-                    prompt,
-                    app.buffer,
-                )?;
-                if app.cursor_offset != 0 {
-                    write!(
-                        app.output,
-                        "{}",
-                        cursor::Left(app.cursor_offset as u16)
-                    )?;
-                }
+            Event::Resize(new_x, new_y) => {
+                app.resize(new_x, new_y);
+            }
+            Event::Mouse(_) | Event::Tick => {}
+            Event::Terminate => {
+                app.terminated = true;
+                break;
             }
             Event::Key(key) => match key {
                 Key::Esc => {
@@ -291,79 +911,54 @@ fn input_state<W: Write>(
                     *state = State::Exit;
                     break;
                 }
-                Key::Delete => {
-                    if app.cursor_offset > 0 {
-                        let index = app.buffer.len() - app.cursor_offset;
-                        app.buffer.remove(index);
-                        app.cursor_offset -= 1;
-                        write!(
-                            app.output,
-                            "{}{} {}",
-                            cursor::Save,
-                            &app.buffer[index..],
-                            cursor::Restore
-                        )?;
-                    }
+                Key::Delete if app.cursor_offset > 0 => {
+                    let index = app.cursor_index();
+                    app.buffer.remove(index);
+                    app.cursor_offset -= 1;
                 }
-                Key::Backspace | Key::Ctrl('h') => {
-                    let length = app.buffer.len();
-                    if app.cursor_offset < length {
-                        let index = length - app.cursor_offset - 1;
+                Key::Backspace | Key::Ctrl('h')
+                    if app.cursor_offset < app.buffer.chars().count() =>
+                {
+                    let end = app.cursor_index();
+                    if let Some((index, _)) =
+                        app.buffer[..end].char_indices().next_back()
+                    {
                         app.buffer.remove(index);
-                        write!(
-                            app.output,
-                            "{}{}{} {}",
-                            cursor::Left(1),
-                            cursor::Save,
-                            &app.buffer[index..],
-                            cursor::Restore
-                        )?;
                     }
                 }
-                Key::Right | Key::Ctrl('f') => {
-                    if app.cursor_offset > 0 {
-                        app.cursor_offset -= 1;
-                        write!(app.output, "{}", cursor::Right(1))?;
-                    }
+                Key::Right | Key::Ctrl('f') if app.cursor_offset > 0 => {
+                    app.cursor_offset -= 1;
                 }
-                Key::Left | Key::Ctrl('b') => {
-                    if app.cursor_offset < app.buffer.len() {
-                        app.cursor_offset += 1;
-                        write!(app.output, "{}", cursor::Left(1))?;
-                    }
+                Key::Left | Key::Ctrl('b')
+                    if app.cursor_offset < app.buffer.chars().count() =>
+                {
+                    app.cursor_offset += 1;
                 }
                 Key::Char('\n') | Key::Ctrl('j') => {
-                    write!(
-                        app.output,
-                        "{}{}\r",
-                        clear::CurrentLine,
-                        cursor::Hide
-                    )?;
-                    app.output.flush()?;
-
-                    if let State::Input = *state {
-                        *state = State::Mutate(Action::AddTask);
+                    if let State::Input(action) = *state {
+                        *state = State::Mutate(action);
                     }
                     tree = mutate_state(state, app, tree);
                     app.buffer.clear();
+                    app.cursor_offset = 0;
 
                     app.list.rebuild(&tree);
                     if let State::Normal = *state {
                         break;
                     }
-                    redraw(&mut app.output, &app.list)?;
                 }
                 Key::Char(c) => {
-                    app.buffer.push(c);
-                    write!(app.output, "{}", c)?;
+                    let index = app.cursor_index();
+                    app.buffer.insert(index, c);
                 }
                 _ => {}
             },
         }
-        app.output.flush()?;
+        redraw(app)?;
     }
 
-    write!(app.output, "{}\r{}", clear::CurrentLine, cursor::Hide)?;
+    app.prompt = None;
+    app.cursor_offset = 0;
     Ok(tree)
 }
 
@@ -372,24 +967,262 @@ fn mutate_state<W: Write>(
     app: &mut App<W>,
     mut tree: Tree,
 ) -> Tree {
-    match state {
-        State::Mutate(action) => match action {
-            Action::AddTask => {
-                let task = Task::new().set_name(app.buffer.clone());
-                tree = tree::add(tree, task);
-                *state = State::Normal;
-            }
-            Action::DeleteTask => {
-                let current_index = app.list.index;
-                let task_index = match tree.nth_child(current_index) {
-                    Ok(a) => a,
-                    _ => unreachable!(),
-                };
-                tree = tree::delete(tree, task_index);
+    let action = match state {
+        State::Mutate(action) => *action,
+        _ => unreachable!(),
+    };
+    let selection = app.list.selection();
+
+    match action {
+        Action::AddTask => {
+            let task = Task::new().set_name(app.buffer.clone());
+            tree = tree::add(tree, task);
+        }
+        Action::DeleteTasks => {
+            // Deleting from the trash shifts indices around
+            app.clipboard.clear();
+            tree = tree::trash_all(tree, &selection);
+        }
+        Action::RestoreTasks => {
+            for idx in selection {
+                tree = tree::restore(tree, idx);
+            }
+        }
+        Action::EmptyTrash => {
+            app.clipboard.clear();
+            tree = tree::empty_trash(tree);
+        }
+        Action::CompleteTasks => {
+            for &idx in selection.iter() {
+                tree = tree::complete(tree, idx);
+            }
+            app.list.fade(&selection);
+        }
+        Action::TagTasks => {
+            let tag = app.buffer.trim().trim_start_matches('#');
+            if !tag.is_empty() {
+                for idx in selection {
+                    tree = tree::tag(tree, idx, tag.to_string());
+                }
+            }
+        }
+        Action::SetDue => match task::parse_due(app.buffer.trim()) {
+            Ok(due) => {
+                for idx in selection {
+                    tree = tree::set_due(tree, idx, due);
+                }
+            }
+            Err(e) => {
+                app.status = Some(format!(
+                    "Invalid date, expected {}: {}",
+                    task::DATE_FORMAT,
+                    e
+                ));
             }
         },
-        _ => unreachable!(),
+        Action::MoveTasks => {
+            let parent = tree.ptr();
+            for idx in app.clipboard.drain(..) {
+                tree = tree::relocate(tree, idx, parent);
+            }
+        }
+    }
+
+    if !matches!(action, Action::AddTask) {
+        app.list.clear_marks();
     }
+    app.unsaved = true;
+    *state = State::Normal;
 
     tree
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::TaskFile;
+    use crate::test_util::scratch_dir;
+
+    fn spawn_list(length: usize, height: usize) -> List {
+        let items = (0..length)
+            .map(|i| (i + 1, format!("Task {}", i)))
+            .collect();
+        List::new(vec![(0, String::from("Root"))], items, height)
+    }
+
+    #[test]
+    fn selection_stays_in_viewport() {
+        let mut list = spawn_list(10, 3);
+        for _ in 0..4 {
+            list.increment();
+        }
+
+        assert_eq!(list.index, 4);
+        assert_eq!(list.offset, 2);
+
+        list.decrement();
+        list.decrement();
+        list.decrement();
+        assert_eq!(list.offset, 1);
+    }
+
+    #[test]
+    fn paging_and_jumps() {
+        let mut list = spawn_list(10, 3);
+        list.page_down();
+        assert_eq!((list.index, list.offset), (3, 1));
+
+        list.last();
+        assert_eq!((list.index, list.offset), (9, 7));
+
+        list.page_up();
+        assert_eq!((list.index, list.offset), (6, 6));
+
+        list.first();
+        assert_eq!((list.index, list.offset), (0, 0));
+    }
+
+    #[test]
+    fn resize_keeps_selection_visible() {
+        let mut list = spawn_list(10, 8);
+        list.last();
+        assert_eq!(list.offset, 2);
+
+        list.resize(2);
+        assert_eq!(list.offset, 8);
+
+        list.resize(20);
+        assert_eq!(list.offset, 0);
+    }
+
+    #[test]
+    fn mouse_hit_testing() {
+        let mut list = spawn_list(10, 3);
+        list.path.push((4, String::from("Work")));
+        list.last();
+
+        assert_eq!(list.item_at(0), None);
+        assert_eq!(list.item_at(1), Some(7));
+        assert_eq!(list.item_at(3), Some(9));
+        assert_eq!(list.item_at(4), None);
+
+        // "Root / Work"
+        assert_eq!(list.crumb_at(0), Some(0));
+        assert_eq!(list.crumb_at(4), None);
+        assert_eq!(list.crumb_at(7), Some(4));
+        assert_eq!(list.crumb_at(11), None);
+
+        let dir = scratch_dir("mouse");
+        let file = Box::new(TaskFile::new(dir.join(".toru.yaml")));
+        let tree = Tree::new();
+        let mut app =
+            App::new(Vec::new(), &tree, Keymap::default(), (80, 24), file);
+        let click = MouseEvent::Press(MouseButton::Left, 0, 0);
+        let tree = mouse_event(&mut app, tree, click);
+        assert_eq!(tree.ptr(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn editing_names_with_multibyte_characters() {
+        let dir = scratch_dir("multibyte");
+        let file = Box::new(TaskFile::new(dir.join(".toru.yaml")));
+        let tree = Tree::new();
+        let app =
+            App::new(Vec::new(), &tree, Keymap::default(), (80, 24), file);
+        let (tx, rx) = mpsc::channel();
+        let keys = [
+            Key::Char('i'),
+            Key::Char('\u{e9}'),
+            Key::Left,
+            Key::Char('a'),
+            Key::Char('x'),
+            Key::Backspace,
+            Key::Right,
+            Key::Char('b'),
+            Key::Left,
+            Key::Delete,
+            Key::Char('\n'),
+        ];
+        for key in keys.iter() {
+            tx.send(Event::Key(*key)).unwrap();
+        }
+        drop(tx);
+
+        let tree = normal_state(&rx, &mut State::Normal, app, tree).unwrap();
+        assert_eq!(tree.children().next().unwrap().name(), "a\u{e9}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn marks_and_visual_selection() {
+        let mut list = spawn_list(6, 6);
+        assert_eq!(list.selection(), vec![1]);
+
+        list.toggle_mark();
+        list.increment();
+        list.visual();
+        list.increment();
+        list.increment();
+        assert_eq!(list.selection(), vec![1, 3, 4, 5]);
+
+        list.visual();
+        list.last();
+        list.toggle_mark();
+        assert_eq!(list.selection(), vec![1, 3, 4, 5, 6]);
+
+        list.first();
+        list.toggle_mark();
+        assert_eq!(list.selection(), vec![3, 4, 5, 6]);
+
+        list.clear_marks();
+        assert_eq!(list.selection(), vec![2]);
+    }
+
+    #[test]
+    fn completed_tasks_fade_out() {
+        let mut tree = Tree::new();
+        tree = tree::add(tree, Task::new().set_name(String::from("a")));
+        tree = tree::add(tree, Task::new().set_name(String::from("b")));
+
+        let mut list = List::from_tree(&tree, 5);
+        tree = tree::complete(tree, 1);
+        list.fade(&[1]);
+        list.rebuild(&tree);
+        assert_eq!(list.items.len(), 2);
+        assert!(list.fade_progress(1).is_some());
+
+        assert!(!list.expire(Instant::now()));
+        assert!(list.expire(Instant::now() + FADE_DURATION));
+        list.rebuild(&tree);
+        assert_eq!(list.items, vec![(2, String::from("b"))]);
+    }
+
+    #[test]
+    fn terminate_saves_changes() {
+        let dir = scratch_dir("terminate");
+        let path = dir.join(".toru.yaml");
+
+        let tree = Tree::new();
+        let file = Box::new(TaskFile::new(path.clone()));
+        let app =
+            App::new(Vec::new(), &tree, Keymap::default(), (80, 24), file);
+        let (tx, rx) = mpsc::channel();
+        for key in "ia\n".chars() {
+            tx.send(Event::Key(Key::Char(key))).unwrap();
+        }
+        // A task half way through being named is dropped
+        for key in "ib".chars() {
+            tx.send(Event::Key(Key::Char(key))).unwrap();
+        }
+        tx.send(Event::Terminate).unwrap();
+        tx.send(Event::Key(Key::Char('i'))).unwrap();
+
+        let tree = normal_state(&rx, &mut State::Normal, app, tree).unwrap();
+        let saved = TaskFile::new(path).load().unwrap();
+        assert_eq!(saved.tasks().len(), 2);
+        assert_eq!(saved.tasks().len(), tree.tasks().len());
+        assert_eq!(saved.children().next().unwrap().name(), "a");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
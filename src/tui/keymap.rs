@@ -0,0 +1,336 @@
+//! This module maps sequences of keys to the [`Operation`]s the TUI performs
+//! in its normal state.
+//!
+//! [`Operation`]: ./enum.Operation.html
+
+use crate::ToruError;
+use std::{collections::HashMap, str::FromStr};
+use termion::event::Key;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    Ascend,
+    Descend,
+    Root,
+    Previous,
+    Next,
+    PageUp,
+    PageDown,
+    First,
+    Last,
+    Add,
+    Delete,
+    Trash,
+    Restore,
+    EmptyTrash,
+    ToggleMark,
+    Visual,
+    ClearMarks,
+    Complete,
+    Tag,
+    Due,
+    Cut,
+    Paste,
+    Quit,
+}
+
+impl FromStr for Operation {
+    type Err = ToruError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascend" => Ok(Self::Ascend),
+            "descend" => Ok(Self::Descend),
+            "root" => Ok(Self::Root),
+            "previous" => Ok(Self::Previous),
+            "next" => Ok(Self::Next),
+            "page_up" => Ok(Self::PageUp),
+            "page_down" => Ok(Self::PageDown),
+            "first" => Ok(Self::First),
+            "last" => Ok(Self::Last),
+            "add" => Ok(Self::Add),
+            "delete" => Ok(Self::Delete),
+            "trash" => Ok(Self::Trash),
+            "restore" => Ok(Self::Restore),
+            "empty_trash" => Ok(Self::EmptyTrash),
+            "toggle_mark" => Ok(Self::ToggleMark),
+            "visual" => Ok(Self::Visual),
+            "clear_marks" => Ok(Self::ClearMarks),
+            "complete" => Ok(Self::Complete),
+            "tag" => Ok(Self::Tag),
+            "due" => Ok(Self::Due),
+            "cut" => Ok(Self::Cut),
+            "paste" => Ok(Self::Paste),
+            "quit" => Ok(Self::Quit),
+            _ => Err(Self::Err::ConfigError(format!("Unknown action '{}'", s))),
+        }
+    }
+}
+
+/// The result of looking up the keys pressed so far.
+#[derive(Debug, PartialEq)]
+pub enum Lookup {
+    Found(Operation),
+    /// The keys are the start of a longer binding, e.g. the first 'g' of 'gg'.
+    Pending,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, Operation>,
+}
+
+impl Keymap {
+    /// Creates a keymap with no bindings at all.
+    pub fn empty() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Binds a key sequence, written the way it is in the config file, to an
+    /// action. Binding to the action "none" removes an existing binding.
+    pub fn bind(&mut self, keys: &str, action: &str) -> Result<(), ToruError> {
+        let keys = parse_keys(keys)?;
+
+        if action == "none" {
+            self.bindings.remove(&keys);
+        } else {
+            self.bindings.insert(keys, action.parse()?);
+        }
+
+        Ok(())
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        if let Some(&operation) = self.bindings.get(keys) {
+            return Lookup::Found(operation);
+        }
+
+        let is_prefix = self.bindings.keys().any(|binding| {
+            binding.len() > keys.len() && binding.starts_with(keys)
+        });
+
+        if is_prefix {
+            Lookup::Pending
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    /// Adds a key to the sequence pressed so far and returns the operation it
+    /// completes, if any. A key that does not continue a pending sequence
+    /// starts a new one.
+    pub fn next(&self, pending: &mut Vec<Key>, key: Key) -> Option<Operation> {
+        pending.push(key);
+
+        let mut lookup = self.lookup(pending);
+        if lookup == Lookup::Unbound && pending.len() > 1 {
+            pending.clear();
+            pending.push(key);
+            lookup = self.lookup(pending);
+        }
+
+        match lookup {
+            Lookup::Found(operation) => {
+                pending.clear();
+                Some(operation)
+            }
+            Lookup::Pending => None,
+            Lookup::Unbound => {
+                pending.clear();
+                None
+            }
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let defaults = [
+            ("h", "ascend"),
+            ("left", "ascend"),
+            ("l", "descend"),
+            ("right", "descend"),
+            ("~", "root"),
+            ("k", "previous"),
+            ("up", "previous"),
+            ("j", "next"),
+            ("down", "next"),
+            ("pageup", "page_up"),
+            ("ctrl-b", "page_up"),
+            ("pagedown", "page_down"),
+            ("ctrl-f", "page_down"),
+            ("home", "first"),
+            ("gg", "first"),
+            ("end", "last"),
+            ("G", "last"),
+            ("i", "add"),
+            ("d", "delete"),
+            ("t", "trash"),
+            ("r", "restore"),
+            ("E", "empty_trash"),
+            ("space", "toggle_mark"),
+            ("V", "visual"),
+            ("esc", "clear_marks"),
+            ("x", "complete"),
+            ("#", "tag"),
+            ("D", "due"),
+            ("m", "cut"),
+            ("p", "paste"),
+            ("q", "quit"),
+        ];
+
+        let mut keymap = Self::empty();
+        for (keys, action) in defaults.iter() {
+            if let Err(e) = keymap.bind(keys, action) {
+                panic!("Invalid default binding {}: {}", keys, e);
+            }
+        }
+
+        keymap
+    }
+}
+
+/// Parses a key sequence such as "j", "ctrl-f", "pagedown" or "g t". A word
+/// repeating one character, such as "gg", is read as that key pressed once
+/// per character, while any other word must be the name of a key so that a
+/// misspelt name is reported rather than read as a sequence.
+fn parse_keys(s: &str) -> Result<Vec<Key>, ToruError> {
+    let mut keys = Vec::new();
+
+    for word in s.split_whitespace() {
+        let mut chars = word.chars();
+        let first = chars.next();
+        match parse_key(word) {
+            Some(key) => keys.push(key),
+            None if chars.all(|c| Some(c) == first) => {
+                keys.extend(word.chars().map(Key::Char))
+            }
+            None => {
+                return Err(ToruError::ConfigError(format!(
+                    "Unknown key '{}', separate the keys of a sequence with \
+                     spaces",
+                    word
+                )));
+            }
+        }
+    }
+
+    if keys.is_empty() {
+        return Err(ToruError::ConfigError(String::from("Empty key binding")));
+    }
+
+    Ok(keys)
+}
+
+/// Strips a modifier such as "ctrl-" from the start of a word, whatever its
+/// case.
+fn strip_modifier<'a>(word: &'a str, modifier: &str) -> Option<&'a str> {
+    match word.get(..modifier.len()) {
+        Some(start) if start.eq_ignore_ascii_case(modifier) => {
+            Some(&word[modifier.len()..])
+        }
+        _ => None,
+    }
+}
+
+fn parse_key(word: &str) -> Option<Key> {
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    if let Some(c) = single(word) {
+        return Some(Key::Char(c));
+    }
+
+    // Modifiers are named in any case, while the key after one keeps its
+    // case, so that "alt-X" is a shifted key. Terminals send every control
+    // key in lower case.
+    if let Some(rest) = strip_modifier(word, "ctrl-") {
+        return single(rest).map(|c| Key::Ctrl(c.to_ascii_lowercase()));
+    }
+    if let Some(rest) = strip_modifier(word, "alt-") {
+        return single(rest).map(Key::Alt);
+    }
+
+    let lower = word.to_lowercase();
+    if let Some(rest) = lower.strip_prefix('f') {
+        if let Ok(n) = rest.parse::<u8>() {
+            return Some(Key::F(n));
+        }
+    }
+
+    let key = match lower.as_str() {
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "esc" => Key::Esc,
+        "enter" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "space" => Key::Char(' '),
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_sequences() {
+        let keymap = Keymap::default();
+        let mut pending = Vec::new();
+
+        assert_eq!(keymap.next(&mut pending, Key::Char('g')), None);
+        assert_eq!(
+            keymap.next(&mut pending, Key::Char('g')),
+            Some(Operation::First)
+        );
+
+        // An unfinished sequence gives way to the next binding
+        assert_eq!(keymap.next(&mut pending, Key::Char('g')), None);
+        assert_eq!(
+            keymap.next(&mut pending, Key::Char('j')),
+            Some(Operation::Next)
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn remapping() {
+        let mut keymap = Keymap::default();
+        keymap.bind("ctrl-n", "next").unwrap();
+        keymap.bind("j", "none").unwrap();
+
+        assert_eq!(
+            keymap.lookup(&[Key::Ctrl('n')]),
+            Lookup::Found(Operation::Next)
+        );
+        assert_eq!(keymap.lookup(&[Key::Char('j')]), Lookup::Unbound);
+        assert!(keymap.bind("x", "explode").is_err());
+
+        keymap.bind("g t", "next").unwrap();
+        assert!(keymap.bind("pgdn", "next").is_err());
+        assert!(keymap.bind("ctrl-enter", "add").is_err());
+
+        assert_eq!(parse_key("Alt-X"), Some(Key::Alt('X')));
+        assert_eq!(parse_key("ALT-x"), Some(Key::Alt('x')));
+        assert_eq!(parse_key("Ctrl-B"), Some(Key::Ctrl('b')));
+        assert_eq!(parse_key("PageDown"), Some(Key::PageDown));
+    }
+}
@@ -0,0 +1,182 @@
+//! This module defines the [`Screen`] structure, a double buffer of terminal
+//! lines. A frame is composed into the back buffer and only the lines that
+//! differ from what is already on the terminal are written out.
+//!
+//! [`Screen`]: ./struct.Screen.html
+
+use std::io::{self, Write};
+use termion::{clear, cursor, style};
+
+#[derive(Clone, Default, PartialEq)]
+struct Line {
+    /// Escape sequences, such as those from `termion::style`, applied to the
+    /// whole line.
+    style: String,
+    text: String,
+}
+
+pub struct Screen {
+    width: u16,
+    height: u16,
+    /// The lines currently shown by the terminal.
+    front: Vec<Line>,
+    /// The lines of the frame being composed.
+    back: Vec<Line>,
+    /// Where to place the cursor once a frame is flushed. A hidden cursor is
+    /// represented by `None`.
+    cursor: Option<(u16, u16)>,
+    /// Set when the terminal contents are unknown, e.g. after a resize, and
+    /// the next flush must repaint everything.
+    invalid: bool,
+}
+
+impl Screen {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            front: vec![Line::default(); height as usize],
+            back: vec![Line::default(); height as usize],
+            cursor: None,
+            invalid: true,
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn resize(&mut self, width: u16, height: u16) {
+        *self = Self::new(width, height);
+    }
+
+    /// Starts a new frame with every line blank and the cursor hidden.
+    pub fn clear(&mut self) {
+        for line in self.back.iter_mut() {
+            line.style.clear();
+            line.text.clear();
+        }
+        self.cursor = None;
+    }
+
+    /// Sets the contents of a row of the frame. Rows are zero based and text
+    /// wider than the terminal is cut off rather than wrapped.
+    pub fn set_line(&mut self, row: usize, text: &str) {
+        self.set_styled_line(row, text, "");
+    }
+
+    /// Sets the contents of a row of the frame along with the style it is
+    /// drawn in.
+    pub fn set_styled_line(&mut self, row: usize, text: &str, style: &str) {
+        if let Some(line) = self.back.get_mut(row) {
+            line.style.clear();
+            line.style.push_str(style);
+            line.text.clear();
+            line.text.extend(text.chars().take(self.width as usize));
+        }
+    }
+
+    /// Shows the cursor at a zero based column and row once the frame is
+    /// flushed.
+    pub fn set_cursor(&mut self, column: u16, row: u16) {
+        self.cursor = Some((column, row));
+    }
+
+    /// Writes the lines that changed since the previous frame.
+    pub fn flush<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        write!(output, "{}", cursor::Hide)?;
+        if self.invalid {
+            write!(output, "{}", clear::All)?;
+        }
+
+        for (row, (back, front)) in
+            self.back.iter().zip(self.front.iter()).enumerate()
+        {
+            if !self.invalid && back == front {
+                continue;
+            }
+
+            write!(
+                output,
+                "{}{}{}{}{}",
+                cursor::Goto(1, row as u16 + 1),
+                back.style,
+                back.text,
+                style::Reset,
+                clear::UntilNewline
+            )?;
+        }
+
+        if let Some((column, row)) = self.cursor {
+            write!(
+                output,
+                "{}{}",
+                cursor::Goto(column + 1, row + 1),
+                cursor::Show
+            )?;
+        }
+
+        self.front.clone_from(&self.back);
+        self.invalid = false;
+        output.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(screen: &mut Screen, lines: &[&str]) -> String {
+        screen.clear();
+        for (row, line) in lines.iter().enumerate() {
+            screen.set_line(row, line);
+        }
+
+        let mut output = Vec::new();
+        screen.flush(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn only_changed_lines_are_written() {
+        let mut screen = Screen::new(20, 3);
+        let first = frame(&mut screen, &["Root", "1. > a", "2.   b"]);
+        assert!(first.contains("1. > a") && first.contains("2.   b"));
+
+        let second = frame(&mut screen, &["Root", "1.   a", "2. > b"]);
+        assert!(!second.contains("Root"));
+        assert!(second.contains("1.   a") && second.contains("2. > b"));
+
+        let third = frame(&mut screen, &["Root", "1.   a", "2. > b"]);
+        assert!(!third.contains("Root") && !third.contains('b'));
+    }
+
+    #[test]
+    fn resize_repaints_everything() {
+        let mut screen = Screen::new(20, 2);
+        frame(&mut screen, &["Root", "1. > a"]);
+
+        screen.resize(3, 2);
+        let output = frame(&mut screen, &["Root", "1. > a"]);
+        assert!(output.contains(&clear::All.to_string()));
+        assert!(output.contains("Roo") && !output.contains("Root"));
+    }
+
+    #[test]
+    fn style_changes_are_redrawn() {
+        let mut screen = Screen::new(20, 1);
+        frame(&mut screen, &["1. > a"]);
+
+        screen.clear();
+        screen.set_styled_line(0, "1. > a", style::CrossedOut.as_ref());
+        let mut output = Vec::new();
+        screen.flush(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(&format!("{}1. > a", style::CrossedOut)));
+    }
+}